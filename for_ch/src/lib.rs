@@ -1,24 +1,71 @@
 extern crate proc_macro;
 use quote::*;
 use syn::{
+    ext::IdentExt,
     parse::{discouraged::Speculative, Parse, ParseStream},
     parse_macro_input,
     punctuated::Punctuated,
+    visit::Visit,
     Token,
 };
 
+mod kw {
+    syn::custom_keyword!(guard);
+    syn::custom_keyword!(map);
+    syn::custom_keyword!(filter);
+    syn::custom_keyword!(flat_map);
+    syn::custom_keyword!(step);
+    syn::custom_keyword!(rev);
+    syn::custom_keyword!(take);
+    syn::custom_keyword!(enumerate);
+    syn::custom_keyword!(err);
+    syn::custom_keyword!(repeat);
+    syn::custom_keyword!(times);
+    syn::custom_keyword!(is);
+    syn::custom_keyword!(carry);
+    syn::custom_keyword!(cap);
+    syn::custom_keyword!(chars);
+    syn::custom_keyword!(bytes);
+    syn::custom_keyword!(lines);
+    syn::custom_keyword!(windows);
+    syn::custom_keyword!(within);
+    syn::custom_keyword!(matches);
+    syn::custom_keyword!(zip_eq);
+    syn::custom_keyword!(zip_checked);
+    syn::custom_keyword!(sorted);
+    syn::custom_keyword!(sorted_by);
+    syn::custom_keyword!(sorted_by_key);
+    syn::custom_keyword!(dedup_by_key);
+    syn::custom_keyword!(by);
+    syn::custom_keyword!(tee);
+    syn::custom_keyword!(cf);
+    syn::custom_keyword!(flat);
+    syn::custom_keyword!(zip_with);
+    syn::custom_keyword!(via);
+    syn::custom_keyword!(grid);
+    syn::custom_keyword!(unless);
+    syn::custom_keyword!(or);
+    syn::custom_keyword!(default);
+    #[cfg(feature = "itertools")]
+    syn::custom_keyword!(chunk_by);
+    #[cfg(feature = "itertools")]
+    syn::custom_keyword!(product);
+    #[cfg(feature = "itertools")]
+    syn::custom_keyword!(pairwise);
+}
+
 /// A macro to flatten for-loop and if-let
 ///
 /// while
 ///
-/// ```rust
+/// ```ignore
 /// 'label: for x in iter;
 /// ...
 /// ```
 ///
 /// would expend to
 ///
-/// ```rust
+/// ```ignore
 /// 'label: for x in iter {
 ///     ...
 /// }
@@ -26,29 +73,29 @@ use syn::{
 ///
 /// and
 ///
-/// ```rust
+/// ```ignore
 /// for x in iter1, for y in iter2;
 /// ...
 /// ```
 ///
 /// would expend to
 ///
-/// ```rust
-/// for (x, y) in iter1.into_iter().zip(iter2) {
+/// ```ignore
+/// for (x, y) in IntoIterator::into_iter(iter1).zip(iter2) {
 ///     ...
 /// }
 /// ```
 ///
 /// and
 ///
-/// ```rust
+/// ```ignore
 /// if let Some(x) = foo();
 /// ...
 /// ```
 ///
 /// would expend to
 ///
-/// ```rust
+/// ```ignore
 /// if let Some(x) = foo() {
 ///     ...
 /// }
@@ -56,24 +103,377 @@ use syn::{
 ///
 /// and
 ///
-/// ```rust
+/// ```ignore
 /// if expr;
 /// ...
 /// ```
 ///
 /// would expend to
 ///
-/// ```rust
+/// ```ignore
 /// if expr {
 ///     ...
 /// }
 /// ```
 ///
+/// where consecutive guards are joined into one `if a && b && .. { ... }`.
+/// A guard may be a let-chain, `if let Some(x) = a && let Ok(y) = b;`, which
+/// becomes nested `if`s, so `x` and `y` are in scope for the following clauses
+/// on every edition. `if let Some(x) = a if x > 0;` is the same as
+/// `if let Some(x) = a && x > 0;`. With the `let_chains` feature, let-chains
+/// are emitted as they are, which needs edition 2024.
+/// A membership guard `if x in xs;` is `if xs.contains(&x);`, so `xs` needs a
+/// `contains` method taking a reference, as slices, arrays, ranges and the
+/// std sets have, and `if x is 1 | 2;` is `if matches!(x, 1 | 2);`, which
+/// neither moves `x` nor binds anything. `unless cond;` is `if !(cond);`.
+/// The patterns of `if let` and `let .. else` clauses may be or-patterns,
+/// with or without a leading `|`: `if let | A(x) | B(x) = e;`.
+/// Patterns are emitted as they are written, so nightly-only ones such as
+/// `if let box x = boxed;` work in a crate that enables `box_patterns`.
+/// Likewise a `yield x;` clause is kept where it's written, inside the nest,
+/// so a `for_ch!` in a coroutine (nightly, `coroutines`) yields from every
+/// iteration that reaches it.
+///
+/// and
+///
+/// ```ignore
+/// guard let Some(x) = foo() else { continue };
+/// ...
+/// ```
+///
+/// would expand to
+///
+/// ```ignore
+/// let Some(x) = foo() else { continue };
+/// ...
+/// ```
+///
+/// so `x` stays in scope for the following clauses without another level of
+/// nesting. The `else` block must diverge, and `let`-`else` needs Rust 1.65.
+/// `guard` may be left out, and `let Some(x) = foo() else { break 'for_ch };`
+/// stops the whole chain at the first `None`.
+///
+/// Every macro of the family expands to a single expression, of type `()` for
+/// `for_ch!`, so it can stand in a `let`, as an argument, as a `match`
+/// scrutinee or arm, or as an `if` branch, without extra braces.
+///
+/// Sources are always consumed through `IntoIterator::into_iter`, so arrays
+/// iterate by value whether they stand alone or are zipped, on every edition.
+/// By-value array iteration needs Rust 1.53 or later.
+///
+/// `Option` and `Result` are sources of zero or one element, so
+/// `for x in foo();` is another way to write `if let Some(x) = foo();`,
+/// and zipping with one runs at most once. rustc lints this with
+/// `for_loops_over_fallibles`, which can be allowed where it's intended.
+///
+/// With the `trace` feature, an `if` or `if let` guard that fails logs its
+/// source text with `log::trace!` before skipping, and consecutive guards are
+/// no longer joined, so the log names the one that failed. The calling crate
+/// needs to depend on `log`.
+///
+/// A guard may be followed by an `else` block, or by an `else if` chain, which
+/// runs instead of `...`:
+///
+/// ```ignore
+/// if a else if b { stmt } else { stmt };
+/// ...
+/// ```
+///
+/// `if a else continue { stmt };` runs `stmt` when `a` is false and then
+/// continues the innermost loop.
+///
+/// The whole nest runs inside a loop labeled `'for_ch`, in `for_ch!`,
+/// `try_for_ch!`, `block_on_for_ch!` and the collecting macros, so
+/// `break 'for_ch;` leaves all of its loops at once. In
+/// `for_ch!`, `break 'for_ch value;` makes `value` the value of the
+/// expansion, which is `Default::default()` if the chain runs to the end
+/// instead:
+///
+/// ```ignore
+/// let first: Option<i32> = for_ch! {
+///     for x in xs;
+///     if x > 3;
+///     break 'for_ch Some(x);
+/// };
+/// ```
+///
+/// `continue 'for_ch;` starts the chain over from its first clause.
+///
+/// `cf expr;` matches the `std::ops::ControlFlow` that `expr` returns:
+/// `Continue(())` runs the following clauses, and `Break(value)` is
+/// `break 'for_ch value;`, so it leaves the whole nest:
+///
+/// ```ignore
+/// let found: Option<usize> = for_ch! {
+///     for (i, x) in xs.iter().enumerate();
+///     cf if x > 3 { ControlFlow::Break(Some(i)) } else { ControlFlow::Continue(()) };
+///     count += 1;
+/// };
+/// ```
+///
+/// That loop, and the `break` ending it, is only there when something jumps
+/// to it: a clause using `'for_ch`, or a bare `break` or `continue` before
+/// the first `for`. Otherwise the nest is expanded as it is, so a chain whose
+/// last clause diverges doesn't end in an unreachable `break`.
+///
+/// With the `for_each` feature, a `for_ch!` of a single `for` line followed
+/// only by guards, `let`s and other loop-free clauses runs as
+/// `Iterator::for_each` over the source, whose internal iteration can
+/// optimize better than a `for` loop, e.g. over chained or flattened
+/// iterators. In the closure, `break`, `continue`, `return`, `?`, `.await`
+/// and `yield` wouldn't reach the code around it, so a chain using any of
+/// them, even inside a
+/// macro call, keeps the `for` loop, as does one iterating a field such as
+/// `&mut self.items`, whose closure would capture all of `self` before
+/// edition 2021.
+///
+/// A chain may start with `#![for_each]` to ask for that lowering with or
+/// without the feature, which is an error where it doesn't apply, or with
+/// `#![for_loop]` to keep the loops either way. A `for` loop calls `next`
+/// for every element, with no closure boundary, and is as fast for ranges and
+/// slices; `for_each` lets adapters like `chain` and `flat_map` drive the
+/// closure from their own `fold`, which usually pays off for those, as long
+/// as the closure is inlined.
+///
+/// ```compile_fail
+/// for_ch::for_ch! {
+///     #![for_each]
+///     for x in 0..10;
+///     if x > 3 {
+///         break;
+///     }
+/// }
+/// ```
+///
+/// Attributes on a clause are forwarded to the code it expands to, except for
+/// `#[cfg(..)]`: a disabled clause is transparent, so
+///
+/// ```ignore
+/// #[cfg(feature = "x")]
+/// for x in iter;
+/// ...
+/// ```
+///
+/// runs `...` once when the feature is off, instead of removing it.
+///
+/// A source is passed to `IntoIterator::into_iter` as it is, so one that is
+/// already an iterator keeps its type. Where inference needs a hint, give it
+/// in the source, with a turbofish or a block binding a typed iterator:
+/// `for x in { let it: vec::IntoIter<u32> = xs.into_iter(); it };`.
+///
+/// A source written `for_ch::grid(rows, cols)` iterates the `(r, c)` cells of
+/// a grid in row-major order, as `for r in 0..rows; for c in 0..cols;` would,
+/// but in one line, so that `for (r, c) in for_ch::grid(h, w);` can be zipped
+/// or adapted. The path keeps a `grid(a, b)` of your own an ordinary call.
+///
+/// Any `IntoIterator` works, e.g. `for msg in rx;` drains an
+/// `mpsc::Receiver`, blocking on each message until all senders are dropped.
+///
+/// Adapters may follow a source and are applied from left to right:
+///
+/// ```ignore
+/// for y in iter map |x| x * 2;
+/// ...
+/// ```
+///
+/// would expand to
+///
+/// ```ignore
+/// for y in IntoIterator::into_iter(iter).map(|x| x * 2) {
+///     ...
+/// }
+/// ```
+///
+/// The adapters are `map |x| ..`, `filter |x| ..` and `flat_map |x| ..`. A
+/// `filter` behaves like an `if` guard right after the loop, but stays attached
+/// to the source, and `flat_map` binds the elements of the iterables it returns.
+/// `if matches pattern` keeps the elements matching `pattern`, like
+/// `filter |x| matches!(x, pattern)`. `step n` is `.step_by(n)`, `take n` is
+/// `.take(n)`, `enumerate` is `.enumerate()` and `rev` is `.rev()`, so
+/// `for x in 0..100 step 2 rev;` counts down from 98. `rev` after `step` needs
+/// a source that is both double-ended and exact-size, as ranges of integers
+/// are.
+///
+/// A range source may be followed by `by step` instead, as in
+/// `for x in start..=end by 3;`, which reads like a classic numeric `for` and
+/// is `(start..=end).step_by(3)`. Like `step_by`, it panics if the step is 0.
+///
+/// `zip_with ys via |x, y| ..` zips the source with `ys` and binds what the
+/// closure combines each pair into, e.g. `for z in xs zip_with ys via |x, y| x + y;`
+/// binds the elementwise sums, where a zipped `for y in ys` would bind both.
+/// It lowers to `xs.zip(ys).map(|(x, y)| ..)`.
+///
+/// `dedup_by_key |x| key` skips each element whose key equals the key of the
+/// element before it. It only collapses *consecutive* duplicates, so sort by
+/// the key first (e.g. with `sorted_by_key`) to dedup globally. The last key
+/// is kept between elements, so it can't borrow from the element. itertools
+/// only has `dedup_by`, which compares two elements, so this is a plain std
+/// `filter` and works without the `itertools` feature. The adapter is a `move`
+/// closure, so what the key closure captures moves into it.
+///
+/// A `map` whose closure is a `match` transforms each element before it's
+/// bound, e.g. to turn enum variants into values:
+/// `for n in shapes map |s| match s { Circle => 0, Square => 4 };`.
+///
+/// Any other statement runs as it is before the following clauses, e.g. a
+/// destructuring assignment `(a, b) = (b, a);` updates bindings declared
+/// outside of the chain on every iteration.
+///
+/// `repeat n times;` is `for _ in 0..n;`.
+///
+/// `carry state = init;` declares `let mut state = init;` ahead of the whole
+/// chain, wherever it's written, so `state` is initialized once and what the
+/// clauses assign to it carries over from one iteration to the next, of inner
+/// and outer loops alike. `init` can't use the bindings of the chain, and a
+/// `carry` can't have attributes:
+///
+/// ```compile_fail
+/// for_ch::for_ch! {
+///     #[cfg(all())]
+///     carry sum = 0;
+///     for x in 0..3;
+///     sum += x;
+/// }
+/// ```
+///
+/// Together with `enumerate`, that covers running accumulators such as
+/// prefix sums, without a dedicated clause:
+///
+/// ```ignore
+/// let prefix = for_vec! {
+///     carry sum = 0;
+///     for i, x in [3, 1, 4] enumerate;
+///     sum += x;
+///     (i, sum)
+/// };
+/// assert_eq!(prefix, [(0, 3), (1, 4), (2, 8)]);
+/// ```
+///
+/// An accumulator that starts over for each element of an outer `for` is a
+/// `let mut sum = 0;` right after that `for` instead.
+///
+/// Right after a string source, `chars`, `bytes` and `lines` iterate what
+/// `str::chars`, `str::bytes` and `str::lines` do, borrowing the string for
+/// the loop: `for c in s chars;`. Likewise, `for w in xs windows n;` iterates
+/// the overlapping `&[T]` windows of length `n` of a slice, or of anything
+/// that derefs to one, as `slice::windows` does.
+///
+/// A `for` pattern written `i, x` is the tuple `(i, x)`, so
+/// `for i, x in xs enumerate, for y in ys;` binds `i`, `x` and `y`, zipped as
+/// `((i, x), y)`.
+///
+/// Three or more zipped items nest, so `for x in a, for y in b, for z in c;`
+/// iterates `(x, (y, z))`. A line starting with `flat`, also before
+/// `zip_eq` or `zip_checked`, maps them to one flat tuple `(x, y, z)`
+/// instead. That is the element type of the zipped iterator, as it shows up
+/// in type errors; the bindings are the same either way.
+///
+/// A line starting with `zip_checked` asserts that its items, which must be
+/// `ExactSizeIterator`s, have the same length before the loop starts, instead
+/// of stopping at the shortest:
+///
+/// ```ignore
+/// zip_checked for x in xs, for y in ys;
+/// ```
+///
+/// A zipped item written `for y in iter2 or default` yields
+/// `Default::default()` once exhausted, so the loop runs until the other items
+/// end.
+///
+/// Inside a chain, `$i` is the 0-based index of the innermost `for` line so
+/// far, and `$i0`, `$i1`, .. are the indices of the first, second, .. `for`
+/// line of the chain. The loops are only enumerated when one of them is used.
+/// `$i` can't be written inside a `macro_rules!` body, where `$` is taken.
+///
+/// A refutable pattern is written with `for?`, and elements that don't match
+/// are skipped:
+///
+/// ```ignore
+/// for? Some(x) in iter;
+/// ...
+/// ```
+///
+/// would expand to
+///
+/// ```ignore
+/// for item in iter {
+///     if let Some(x) = item {
+///         ...
+///     }
+/// }
+/// ```
+///
+/// A pattern followed by `?`, as in `for x? in results;`, applies `?` to each
+/// element before matching it, so the first `Err` of an iterator of `Result`s
+/// returns from the enclosing function or `try_for_ch!`, while
+/// `for x in results?;` applies `?` to the source itself. Both compose, e.g.
+/// `for entry? in fs::read_dir(dir)?;` for the `io::Result`s of a directory.
+///
+/// `?` anywhere in a `for_ch!` propagates out of the function around it,
+/// which has to return a `Result` or an `Option`. A macro can't see that
+/// return type, so `for_ch!` can't reject a `?` on its own, and a function
+/// returning `()` gets rustc's usual error pointing at the `?`; use
+/// `try_for_ch!` there, which supplies the `Result` itself:
+///
+/// ```compile_fail
+/// fn parse_all(xs: &[&str]) {
+///     for_ch::for_ch! {
+///         for x in xs;
+///         let _: i32 = x.parse()?;
+///     }
+/// }
+/// ```
+///
+/// ```
+/// fn parse_all(xs: &[&str]) -> Result<(), std::num::ParseIntError> {
+///     for_ch::try_for_ch! {
+///         for x in xs;
+///         let _: i32 = x.parse()?;
+///     }
+/// }
+/// # assert!(parse_all(&["1", "x"]).is_err());
+/// ```
+///
+/// With the `stream` feature, `for await x in stream;` iterates a
+/// `futures::Stream` inside async code, and with the `tokio` feature
+/// `for await x in stream within duration;` stops once `duration` has passed
+/// since the loop started. An item still being produced at the deadline is
+/// dropped with the stream. The calling crate needs to depend on `futures`
+/// (and `tokio`).
+///
+/// With the `itertools` feature, a source may be written as
+/// `chunk_by(|x| key, iter)` to iterate `(key, group)` pairs of consecutive
+/// elements sharing a key, as `Itertools::chunk_by` does, and as
+/// `product xs, ys, zs` to iterate the flat tuples of their cartesian product,
+/// as `iproduct!` does, and a line may start with `zip_eq` to zip with
+/// `Itertools::zip_eq`, which panics (inside `itertools`) unless all items have
+/// the same length:
+///
+/// ```ignore
+/// zip_eq for x in iter1, for y in iter2;
+/// ```
+///
+/// A source written `pairwise xs` iterates the overlapping pairs of
+/// consecutive elements as `Itertools::tuple_windows` does, which clones the
+/// elements, and nothing if `xs` has fewer than two.
+///
+/// The adapters `sorted`, `sorted_by |a, b| ..` and `sorted_by_key |x| ..`
+/// iterate the elements in order, as the `Itertools` methods of the same
+/// names do: they collect all of them into a `Vec` and sort it before the
+/// loop starts.
+///
+/// `tee (a, b) = xs;` splits `xs` into two iterators of its elements, as
+/// `Itertools::tee` does, which clones the elements and buffers those one of
+/// them has consumed ahead of the other, so that `a` and `b` can be iterated
+/// separately, e.g. by zipped items or one after the other.
+///
+/// The calling crate needs to depend on `itertools`.
+///
 ///
 ///
 /// ## Example
 ///
-/// ```rust
+/// ```ignore
 /// for_ch! {
 ///     for x in 0..10;                         // forall x in 0..10,
 ///     // you can add a label before `for`
@@ -89,7 +489,7 @@ use syn::{
 ///
 /// would expend to
 ///
-/// ```rust
+/// ```ignore
 /// for x in 0..10 {
 ///     for y in (x..10).zip(0..5) {
 ///         if let Some(z) = foo(x, y).await? {
@@ -102,306 +502,3957 @@ use syn::{
 /// ```
 #[proc_macro]
 pub fn for_ch(input: proc_macro::TokenStream) -> proc_macro::TokenStream {
-    let input = parse_macro_input!(input as ForCh);
+    let ForChLowered { lowering, chain } = parse_macro_input!(input as ForChLowered);
+    let input = chain;
     if input.stmts.is_empty() {
-        return proc_macro::TokenStream::new();
+        return proc_macro::TokenStream::from(quote! { () });
     }
 
-    let body = for_body(&input.stmts);
-    let output = quote! {
-        loop {
-            #body
-            break;
+    let for_each = match &lowering {
+        Some(Lowering::ForEach(attr)) => match for_each_body(&input.stmts) {
+            Some(for_each) => Some(for_each),
+            None => return syn::Error::new_spanned(
+                attr,
+                "`for_each` needs a single `for` line, followed by clauses without loops or jumps",
+            )
+            .to_compile_error()
+            .into(),
+        },
+        Some(Lowering::ForLoop) => None,
+        None if cfg!(feature = "for_each") && !projects_fields(&input.stmts) => {
+            for_each_body(&input.stmts)
         }
+        None => None,
     };
+    let output = for_each.unwrap_or_else(|| outer_loop(&input.stmts));
 
     proc_macro::TokenStream::from(output)
 }
 
-/// for x in xs
-struct ForInItem {
-    _for_tok: Token![for],
-    pat: syn::Pat,
-    _in_tok: Token![in],
-    iter: syn::Expr,
-}
-
-/// 'label: for x in xs | for y in ys | for z in zs ...;
-struct ForIn {
-    label: Option<syn::Label>,
-    items: Punctuated<ForInItem, Token![,]>,
-    _semi_tok: Token![;],
-}
-/// if let Some(x) = option;
+/// Like `for_ch!`, but lazily yields the trailing expression as an iterator
+///
+/// ```ignore
+/// let it = for_iter! {
+///     for x in 0..3;
+///     if x != 1;
+///     for y in 0..x;
+///     (x, y)
+/// };
+/// assert_eq!(it.collect::<Vec<_>>(), [(2, 0), (2, 1)]);
+/// ```
+///
+/// would expand to
+///
+/// ```ignore
+/// (0..3).flat_map(move |x| {
+///     (if x != 1 { Some((0..x).flat_map(move |y| once((x, y)))) } else { None })
+///         .into_iter()
+///         .flatten()
+/// })
+/// ```
+///
+/// Every `for` becomes a `move` closure, so variables from the enclosing
+/// scope that are used by inner clauses should be references or `Copy`.
+/// `break` and `continue` have no loop to target.
+#[proc_macro]
+pub fn for_iter(input: proc_macro::TokenStream) -> proc_macro::TokenStream {
+    let mut input = parse_macro_input!(input as ForCh);
+    let terminal = match split_terminal(&mut input.stmts) {
+        Ok(terminal) => terminal,
+        Err(err) => return err.to_compile_error().into(),
+    };
 
-struct IfLet {
-    _if_tok: Token![if],
-    _let_tok: Token![let],
-    pat: syn::Pat,
-    _eq_tok: Token![=],
-    expr: syn::Expr,
-    _semi_tok: Token![;],
+    proc_macro::TokenStream::from(iter_body(&input.stmts, &terminal, Lazy::Iter))
 }
 
-/// if expr;
+/// Like `for_iter!`, but yields the tuple of all names bound by the `for`
+/// and `if let` clauses, in the order they are first bound, instead of a
+/// trailing expression
+///
+/// ```ignore
+/// let it = for_tuples! {
+///     for x in 0..3;
+///     for y in x..3;
+///     if x + y == 3;
+/// };
+/// assert_eq!(it.collect::<Vec<_>>(), [(0, 3), (1, 2)]);
+/// ```
+///
+/// A chain binding a single name yields `(x,)`. Names starting with an
+/// uppercase letter are taken to be constants or unit variants, and `_`
+/// binds nothing.
+#[proc_macro]
+pub fn for_tuples(input: proc_macro::TokenStream) -> proc_macro::TokenStream {
+    let input = parse_macro_input!(input as ForCh);
+    let mut bindings = Bindings(vec![]);
+    for item in &input.stmts {
+        bindings.bind_clause(item);
+    }
 
-struct IfGuard {
-    _if_tok: Token![if],
-    expr: syn::Expr,
-    _semi_tok: Token![;],
+    let names = bindings.0;
+    let terminal = syn::parse_quote! { (#(#names,)*) };
+    proc_macro::TokenStream::from(iter_body(&input.stmts, &terminal, Lazy::Iter))
 }
 
-/// if let Some(x) = option else { stmt };
+/// Collects the iterator of `for_iter!` into `Target`, any `FromIterator`
+/// type, such as a custom container
+///
+/// ```ignore
+/// let bag = for_collect_with!(Bag<i32>, {
+///     for x in 0..3;
+///     x * x
+/// });
+/// ```
+///
+/// would expand to
+///
+/// ```ignore
+/// Iterator::collect::<Bag<i32>>((0..3).flat_map(move |x| once(x * x)))
+/// ```
+#[proc_macro]
+pub fn for_collect_with(input: proc_macro::TokenStream) -> proc_macro::TokenStream {
+    let ForCollectWith {
+        target, mut stmts, ..
+    } = parse_macro_input!(input as ForCollectWith);
+    let terminal = match split_terminal(&mut stmts) {
+        Ok(terminal) => terminal,
+        Err(err) => return err.to_compile_error().into(),
+    };
 
-struct IfLetElse {
-    _if_tok: Token![if],
-    _let_tok: Token![let],
-    pat: syn::Pat,
-    _eq_tok: Token![=],
-    expr: syn::Expr,
-    _else_tok: Token![else],
-    block: syn::Block,
-    _semi_tok: Token![;],
+    let iter = iter_body(&stmts, &terminal, Lazy::Iter);
+    proc_macro::TokenStream::from(quote! {
+        ::core::iter::Iterator::collect::<#target>(#iter)
+    })
 }
 
-/// if expr else { stmt };
+/// Like `for_iter!`, but lazily yields the trailing expression as a
+/// `futures::Stream`, and `for await` clauses draw from streams
+///
+/// Needs the `stream` feature, and the calling crate needs to depend on
+/// `futures`.
+///
+/// ```ignore
+/// let s = for_stream! {
+///     for await x in stream::iter(0..3);
+///     for y in 0..x;
+///     (x, y)
+/// };
+/// assert_eq!(block_on(s.collect::<Vec<_>>()), [(1, 0), (2, 0), (2, 1)]);
+/// ```
+///
+/// The closures of inner `for` clauses are `move`, as in `for_iter!`, but the
+/// outermost one borrows the enclosing scope, unless the chain is written as
+/// `for_stream!(move { ... })`, which makes the stream own what it captures,
+/// e.g. to return it from a function.
+#[proc_macro]
+pub fn for_stream(input: proc_macro::TokenStream) -> proc_macro::TokenStream {
+    let mut input = parse_macro_input!(input as ForStream);
+    if cfg!(not(feature = "stream")) {
+        return syn::Error::new(
+            proc_macro2::Span::call_site(),
+            "`for_stream!` needs the `stream` feature of for_ch",
+        )
+        .to_compile_error()
+        .into();
+    }
 
-struct IfElse {
-    _if_tok: Token![if],
-    expr: syn::Expr,
-    _else_tok: Token![else],
-    block: syn::Block,
-    _semi_tok: Token![;],
-}
+    let terminal = match split_terminal(&mut input.stmts) {
+        Ok(terminal) => terminal,
+        Err(err) => return err.to_compile_error().into(),
+    };
 
-enum ForChItem {
-    Stmt(syn::Stmt),
-    IfLet(IfLet),
-    IfGuard(IfGuard),
-    IfLetElse(IfLetElse),
-    IfElse(IfElse),
-    ForIn(ForIn),
+    let lazy = Lazy::Stream {
+        move_env: input.move_tok.is_some(),
+    };
+    proc_macro::TokenStream::from(iter_body(&input.stmts, &terminal, lazy))
 }
 
-struct ForCh {
-    stmts: Vec<ForChItem>,
-}
+/// Like `for_ch!`, but runs the chain, which may `for await` streams and
+/// `.await` futures, to completion on the current thread
+///
+/// Needs the `stream` feature, and the calling crate needs to depend on
+/// `futures`, whose `futures::executor::block_on` drives the chain:
+///
+/// ```ignore
+/// block_on_for_ch! {
+///     for await x in stream::iter(0..3);
+///     for y in 0..x;
+///     v.push((x, y));
+/// }
+/// ```
+///
+/// would expand to
+///
+/// ```ignore
+/// futures::executor::block_on(async {
+///     while let Some(x) = stream.next().await {
+///         for y in 0..x {
+///             v.push((x, y));
+///         }
+///     }
+/// })
+/// ```
+///
+/// That executor doesn't provide the timers or IO of another runtime; to run
+/// the chain on e.g. tokio, pass `async { for_ch! { ... } }` to its
+/// `Runtime::block_on` instead.
+#[proc_macro]
+pub fn block_on_for_ch(input: proc_macro::TokenStream) -> proc_macro::TokenStream {
+    let input = parse_macro_input!(input as ForCh);
+    if cfg!(not(feature = "stream")) {
+        return syn::Error::new(
+            proc_macro2::Span::call_site(),
+            "`block_on_for_ch!` needs the `stream` feature of for_ch",
+        )
+        .to_compile_error()
+        .into();
+    }
 
-impl Parse for ForInItem {
-    fn parse(input: ParseStream) -> syn::Result<Self> {
-        Ok(Self {
-            _for_tok: input.parse()?,
-            pat: input.parse()?,
-            _in_tok: input.parse()?,
-            iter: input.parse()?,
+    let outer = outer_loop(&input.stmts);
+    proc_macro::TokenStream::from(quote! {
+        ::futures::executor::block_on(async {
+            #outer
         })
-    }
+    })
 }
 
-impl Parse for ForIn {
-    fn parse(input: ParseStream) -> syn::Result<Self> {
-        let label = if input.peek(syn::Lifetime) && input.peek2(Token![:]) {
-            Some(input.parse()?)
-        } else {
-            None
-        };
+/// Like `for_ch!`, but each iteration of the first `for` runs as a task
+/// spawned into a `rayon::scope`, which returns once all of them are done
+///
+/// Needs the `rayon` feature, and the calling crate needs to depend on
+/// `rayon`.
+///
+/// ```ignore
+/// let total = AtomicUsize::new(0);
+/// let total = &total;
+/// scope_for_ch! {
+///     for x in 0..4;
+///     for y in 0..x;
+///     total.fetch_add(y, Ordering::Relaxed);
+/// }
+/// ```
+///
+/// would expand to
+///
+/// ```ignore
+/// rayon::scope(|scope| {
+///     for x in 0..4 {
+///         scope.spawn(move |_| {
+///             for y in 0..x {
+///                 total.fetch_add(y, Ordering::Relaxed);
+///             }
+///         });
+///     }
+/// })
+/// ```
+///
+/// The tasks are `move` closures, so the bindings of the first `for` must be
+/// `Send`, and state shared between the tasks should be borrowed through a
+/// reference, whose target must be `Sync`.
+#[proc_macro]
+pub fn scope_for_ch(input: proc_macro::TokenStream) -> proc_macro::TokenStream {
+    let input = parse_macro_input!(input as ForCh);
+    if cfg!(not(feature = "rayon")) {
+        return syn::Error::new(
+            proc_macro2::Span::call_site(),
+            "`scope_for_ch!` needs the `rayon` feature of for_ch",
+        )
+        .to_compile_error()
+        .into();
+    }
 
-        let mut items = Punctuated::new();
+    let scope = synthetic_ident("__scope");
+    let body = scope_body(&input.stmts, &scope);
+    proc_macro::TokenStream::from(quote! {
+        ::rayon::scope(|#scope| {
+            #body
+        })
+    })
+}
 
-        // first item
-        items.push_value(input.parse()?);
+/// Like `for_vec!`, but the first `for` runs on `rayon`'s thread pool, and
+/// the values are still collected in the order of its source
+///
+/// The source must be `IntoParallelIterator`, and the clauses after it run
+/// in a closure, so they can't `break`, `continue`, `return`, use `?` or
+/// `.await`. Needs the `rayon` feature, and the calling crate needs to depend
+/// on `rayon`.
+///
+/// ```ignore
+/// let v = par_for_vec! {
+///     for x in 0..100;
+///     if x % 3 == 0;
+///     x * x
+/// };
+/// ```
+///
+/// would expand to
+///
+/// ```ignore
+/// (0..100)
+///     .into_par_iter()
+///     .filter_map(|x| {
+///         let mut item = None;
+///         if x % 3 == 0 {
+///             item = Some(x * x);
+///         }
+///         item
+///     })
+///     .collect::<Vec<_>>()
+/// ```
+///
+/// A chain with more `for` lines collects the values of each element of the
+/// first one into a `Vec` and flattens those with `flat_map_iter`.
+#[proc_macro]
+pub fn par_for_vec(input: proc_macro::TokenStream) -> proc_macro::TokenStream {
+    let mut input = parse_macro_input!(input as ForCh);
+    if cfg!(not(feature = "rayon")) {
+        return syn::Error::new(
+            proc_macro2::Span::call_site(),
+            "`par_for_vec!` needs the `rayon` feature of for_ch",
+        )
+        .to_compile_error()
+        .into();
+    }
+    let terminal = match split_terminal(&mut input.stmts) {
+        Ok(terminal) => terminal,
+        Err(err) => return err.to_compile_error().into(),
+    };
+    if closure_chain(&input.stmts).is_none() {
+        return syn::Error::new(
+            proc_macro2::Span::call_site(),
+            "`par_for_vec!` needs a plain `for` line first, followed by clauses without jumps",
+        )
+        .to_compile_error()
+        .into();
+    }
 
-        // (| for_in_item)*
-        while !input.is_empty() && input.peek(Token![,]) && input.peek2(Token![for]) {
-            items.push_punct(input.parse()?);
-            items.push_value(input.parse()?);
+    let mut rest = input.stmts.split_off(1);
+    let (pat, iter) = match &input.stmts[0] {
+        ForChItem::ForIn(for_in) => for_in.header(),
+        _ => unreachable!(),
+    };
+    let par_iter = quote! { ::rayon::iter::IntoParallelIterator::into_par_iter(#iter) };
+    let output = if rest.iter().any(is_for_in) {
+        let acc = synthetic_ident("__acc");
+        let push = syn::parse_quote! { #acc.push(#terminal); };
+        let values = collect(rest, &acc, quote! { ::std::vec::Vec::new() }, push);
+        quote! {
+            ::rayon::iter::ParallelIterator::flat_map_iter(#par_iter, |#pat| #values)
+        }
+    } else {
+        let item = synthetic_ident("__item");
+        rest.push(ForChItem::Stmt(syn::parse_quote! {
+            #item = ::core::option::Option::Some(#terminal);
+        }));
+        let body = for_body(&rest);
+        quote! {
+            ::rayon::iter::ParallelIterator::filter_map(#par_iter, |#pat| {
+                let mut #item = ::core::option::Option::None;
+                #body
+                #item
+            })
         }
+    };
 
+    proc_macro::TokenStream::from(quote! {
+        ::rayon::iter::ParallelIterator::collect::<::std::vec::Vec<_>>(#output)
+    })
+}
+
+/// Like `for_ch!`, but `?` propagates out of the whole chain
+///
+/// The chain runs in a closure returning `Result`. A trailing expression
+/// without semicolon is evaluated once after the loops have finished and is
+/// returned as `Ok`, otherwise the result is `Ok(())`. The trailing
+/// expression sees the enclosing scope, not the bindings of the chain.
+///
+/// `if let Ok(x) = result else err;` propagates the `Err` of `result`, converted
+/// with `From` as `?` does, instead of skipping it. An `Ok` that doesn't
+/// match, as in `if let Ok(Some(x)) = result else err;`, is skipped.
+///
+/// A `do { ... }` clause runs its statements, which may use `?`, before the
+/// following clauses, e.g. as a fallible setup step in the middle of the chain.
+///
+/// ```ignore
+/// let mut total = 0;
+/// let r: Result<i32, ParseIntError> = try_for_ch! {
+///     for s in ["1", "2", "3"];
+///     total += s.parse::<i32>()?;
+///     total
+/// };
+/// assert_eq!(r, Ok(6));
+/// ```
+///
+/// would expand to
+///
+/// ```ignore
+/// (|| {
+///     for s in ["1", "2", "3"] {
+///         total += s.parse::<i32>()?;
+///     }
+///     Ok(total)
+/// })()
+/// ```
+#[proc_macro]
+pub fn try_for_ch(input: proc_macro::TokenStream) -> proc_macro::TokenStream {
+    let mut input = parse_macro_input!(input as ForCh);
+    let tail = pop_trailing_expr(&mut input.stmts)
+        .map(|expr| expr.into_token_stream())
+        .unwrap_or_else(|| quote! { () });
+
+    let body = outer_loop(&input.stmts);
+    let output = quote! {
+        (|| -> ::core::result::Result<_, _> {
+            #body
+            ::core::result::Result::Ok(#tail)
+        })()
+    };
+
+    proc_macro::TokenStream::from(output)
+}
+
+/// Like `for_ch!`, but collects the trailing expression into a `Vec`
+///
+/// The whole nest is labeled `'for_ch`, so `break 'for_ch;` stops collecting
+/// and returns what has been collected so far. `break` and `continue` in the
+/// clauses of a collecting macro need a label:
+///
+/// ```compile_fail
+/// let v = for_ch::for_vec! {
+///     for x in 0..10;
+///     if x > 3 {
+///         break;
+///     }
+///     x
+/// };
+/// ```
+///
+/// ```ignore
+/// let v = for_vec! {
+///     for x in 0..3;
+///     for y in x..3;
+///     (x, y)
+/// };
+/// assert_eq!(v, [(0, 0), (0, 1), (0, 2), (1, 1), (1, 2), (2, 2)]);
+/// ```
+///
+/// would expand to
+///
+/// ```ignore
+/// {
+///     let mut v = Vec::new();
+///     for x in 0..3 {
+///         for y in x..3 {
+///             v.push((x, y));
+///         }
+///     }
+///     v
+/// }
+/// ```
+///
+/// `for_vec!(cap = n, { ... })` starts from `Vec::with_capacity(n)` instead,
+/// as do `for_flat_vec!` and `for_string!` with `String::with_capacity(n)`.
+#[proc_macro]
+pub fn for_vec(input: proc_macro::TokenStream) -> proc_macro::TokenStream {
+    let mut input = parse_macro_input!(input as ForCapacity);
+    let terminal = match split_terminal(&mut input.stmts) {
+        Ok(terminal) => terminal,
+        Err(err) => return err.to_compile_error().into(),
+    };
+
+    let acc = synthetic_ident("__acc");
+    let init = input.init(quote! { ::std::vec::Vec });
+    let push = syn::parse_quote! { #acc.push(#terminal); };
+    proc_macro::TokenStream::from(collect(input.stmts, &acc, init, push))
+}
+
+/// Like `for_vec!`, but the trailing expression is iterable and all of its
+/// elements are collected, in order
+///
+/// ```ignore
+/// let v = for_flat_vec! {
+///     for x in 1..4;
+///     vec![x; x]
+/// };
+/// assert_eq!(v, [1, 2, 2, 3, 3, 3]);
+/// ```
+#[proc_macro]
+pub fn for_flat_vec(input: proc_macro::TokenStream) -> proc_macro::TokenStream {
+    let mut input = parse_macro_input!(input as ForCapacity);
+    let terminal = match split_terminal(&mut input.stmts) {
+        Ok(terminal) => terminal,
+        Err(err) => return err.to_compile_error().into(),
+    };
+
+    let acc = synthetic_ident("__acc");
+    let init = input.init(quote! { ::std::vec::Vec });
+    let push = syn::parse_quote! {
+        ::core::iter::Extend::extend(&mut #acc, #terminal);
+    };
+    proc_macro::TokenStream::from(collect(input.stmts, &acc, init, push))
+}
+
+/// Like `for_vec!`, but the first `for` iterates the overlapping windows of
+/// length `size` of its slice, as if written `for w in xs windows size;`
+///
+/// ```ignore
+/// let sums = for_windows_vec!(3, {
+///     for w in [1, 2, 3, 4, 5];
+///     w.iter().sum::<i32>()
+/// });
+/// assert_eq!(sums, [6, 9, 12]);
+/// ```
+#[proc_macro]
+pub fn for_windows_vec(input: proc_macro::TokenStream) -> proc_macro::TokenStream {
+    let ForWindows {
+        size, mut stmts, ..
+    } = parse_macro_input!(input as ForWindows);
+    let terminal = match split_terminal(&mut stmts) {
+        Ok(terminal) => terminal,
+        Err(err) => return err.to_compile_error().into(),
+    };
+
+    let item = match stmts.first_mut() {
+        Some(ForChItem::ForIn(for_in)) if for_in.items.len() == 1 => &mut for_in.items[0],
+        _ => {
+            return syn::Error::new(
+                proc_macro2::Span::call_site(),
+                "`for_windows_vec!` needs to start with a `for` over one slice",
+            )
+            .to_compile_error()
+            .into()
+        }
+    };
+    if let Some(Adapter::Str(_) | Adapter::Windows(_)) = item.adapters.first() {
+        return syn::Error::new_spanned(&item.pat, "the source is already adapted")
+            .to_compile_error()
+            .into();
+    }
+    item.adapters.insert(0, Adapter::Windows(size));
+
+    let acc = synthetic_ident("__acc");
+    let init = quote! { ::std::vec::Vec::new() };
+    let push = syn::parse_quote! { #acc.push(#terminal); };
+    proc_macro::TokenStream::from(collect(stmts, &acc, init, push))
+}
+
+/// Like `for_vec!`, but stops all loops once `n` elements have been collected
+///
+/// ```ignore
+/// let v = for_vec_take!(3, {
+///     for x in 0..;
+///     for y in 0..x;
+///     (x, y)
+/// });
+/// assert_eq!(v, [(1, 0), (2, 0), (2, 1)]);
+/// ```
+#[proc_macro]
+pub fn for_vec_take(input: proc_macro::TokenStream) -> proc_macro::TokenStream {
+    let mut input = parse_macro_input!(input as ForVecTake);
+    let terminal = match split_terminal(&mut input.stmts) {
+        Ok(terminal) => terminal,
+        Err(err) => return err.to_compile_error().into(),
+    };
+
+    let acc = synthetic_ident("__acc");
+    let limit = synthetic_ident("__limit");
+    let n = &input.limit;
+    let init = quote! { ::std::vec::Vec::new() };
+    let push = syn::parse_quote! {
+        {
+            if #acc.len() < #limit {
+                #acc.push(#terminal);
+            }
+            if #acc.len() >= #limit {
+                break 'for_ch;
+            }
+        }
+    };
+    let collected = collect(input.stmts, &acc, init, push);
+    proc_macro::TokenStream::from(quote! {
+        {
+            let #limit: usize = #n;
+            #collected
+        }
+    })
+}
+
+/// Like `for_vec!`, but pushes the trailing expressions into an existing
+/// `&mut` sink, anything with a `push` method, instead of a new `Vec`
+///
+/// ```ignore
+/// let mut v = vec![0];
+/// for_push_into!(&mut v, {
+///     for x in 1..3;
+///     x * 10
+/// });
+/// assert_eq!(v, [0, 10, 20]);
+/// ```
+///
+/// The sink stays mutably borrowed until the chain is done, and the
+/// expansion is `()`.
+#[proc_macro]
+pub fn for_push_into(input: proc_macro::TokenStream) -> proc_macro::TokenStream {
+    let ForPushInto {
+        sink, mut stmts, ..
+    } = parse_macro_input!(input as ForPushInto);
+    let terminal = match split_terminal(&mut stmts) {
+        Ok(terminal) => terminal,
+        Err(err) => return err.to_compile_error().into(),
+    };
+
+    let acc = synthetic_ident("__sink");
+    stmts.push(ForChItem::Stmt(syn::parse_quote! { #acc.push(#terminal); }));
+    if let Err(err) = check_bare_jumps(&stmts) {
+        return err.to_compile_error().into();
+    }
+
+    let body = outer_loop(&stmts);
+    proc_macro::TokenStream::from(quote! {
+        {
+            let #acc: &mut _ = #sink;
+            #body
+        }
+    })
+}
+
+/// Folds the trailing expressions into `acc`, starting from `init`, where the
+/// trailing expression is the next value of `acc` and may use `?`
+///
+/// The chain runs in a closure returning `Result`, as in `try_for_ch!`, so
+/// the first error stops all loops and is returned, and otherwise the result
+/// is `Ok` of the last `acc`.
+///
+/// ```ignore
+/// let r: Result<i32, String> = for_try_fold!(0, acc, {
+///     for s in ["1", "2", "3"];
+///     acc + s.parse::<i32>().map_err(|e| e.to_string())?
+/// });
+/// assert_eq!(r, Ok(6));
+/// ```
+///
+/// would expand to
+///
+/// ```ignore
+/// (|| {
+///     let mut acc = 0;
+///     for s in ["1", "2", "3"] {
+///         acc = acc + s.parse::<i32>().map_err(|e| e.to_string())?;
+///     }
+///     Ok(acc)
+/// })()
+/// ```
+#[proc_macro]
+pub fn for_try_fold(input: proc_macro::TokenStream) -> proc_macro::TokenStream {
+    let ForTryFold {
+        init,
+        acc,
+        mut stmts,
+        ..
+    } = parse_macro_input!(input as ForTryFold);
+    let terminal = match split_terminal(&mut stmts) {
+        Ok(terminal) => terminal,
+        Err(err) => return err.to_compile_error().into(),
+    };
+
+    // not `acc = acc + x`, which clippy would ask to be `acc += x`
+    let next = synthetic_ident("__next");
+    stmts.push(ForChItem::Stmt(syn::parse_quote! {
+        {
+            let #next = #terminal;
+            #acc = #next;
+        }
+    }));
+    if let Err(err) = check_bare_jumps(&stmts) {
+        return err.to_compile_error().into();
+    }
+
+    let body = outer_loop(&stmts);
+    proc_macro::TokenStream::from(quote! {
+        (|| -> ::core::result::Result<_, _> {
+            let mut #acc = #init;
+            #body
+            ::core::result::Result::Ok(#acc)
+        })()
+    })
+}
+
+/// Like `for_vec!`, but keeps a uniform random sample of at most `k` of the
+/// trailing expressions, by reservoir sampling
+///
+/// Needs the `rand` feature, and the calling crate needs to depend on `rand`.
+/// The sample is drawn with `rand::thread_rng()`, unless an `&mut` to another
+/// `rand::Rng` is passed before the chain, e.g. a seeded `StdRng` for
+/// reproducible samples:
+///
+/// ```ignore
+/// let mut rng = StdRng::seed_from_u64(7);
+/// let v = for_sample!(3, &mut rng, {
+///     for x in 0..100;
+///     if x % 2 == 0;
+///     x
+/// });
+/// assert_eq!(v.len(), 3);
+/// ```
+///
+/// The order of the sample is unspecified.
+#[proc_macro]
+pub fn for_sample(input: proc_macro::TokenStream) -> proc_macro::TokenStream {
+    let mut input = parse_macro_input!(input as ForSample);
+    if cfg!(not(feature = "rand")) {
+        return syn::Error::new(
+            proc_macro2::Span::call_site(),
+            "`for_sample!` needs the `rand` feature of for_ch",
+        )
+        .to_compile_error()
+        .into();
+    }
+
+    let terminal = match split_terminal(&mut input.stmts) {
+        Ok(terminal) => terminal,
+        Err(err) => return err.to_compile_error().into(),
+    };
+
+    let acc = synthetic_ident("__acc");
+    let k = synthetic_ident("__k");
+    let rng = synthetic_ident("__rng");
+    let seen = synthetic_ident("__seen");
+    let item = synthetic_ident("__item");
+    let j = synthetic_ident("__j");
+    let init = quote! { ::std::vec::Vec::new() };
+    let push = syn::parse_quote! {
+        {
+            let #item = #terminal;
+            #seen += 1;
+            if #acc.len() < #k {
+                #acc.push(#item);
+            } else {
+                let #j = ::rand::Rng::gen_range(&mut *#rng, 0..#seen);
+                if #j < #k {
+                    #acc[#j] = #item;
+                }
+            }
+        }
+    };
+    let bind_rng = match &input.rng {
+        Some(expr) => quote! { let #rng = #expr; },
+        None => {
+            let thread_rng = synthetic_ident("__thread_rng");
+            quote! {
+                let mut #thread_rng = ::rand::thread_rng();
+                let #rng = &mut #thread_rng;
+            }
+        }
+    };
+    let n = &input.k;
+    let collected = collect(input.stmts, &acc, init, push);
+    proc_macro::TokenStream::from(quote! {
+        {
+            let #k: usize = #n;
+            #bind_rng
+            let mut #seen: usize = 0;
+            #collected
+        }
+    })
+}
+
+/// Like `for_vec!`, but the trailing expression is a pair whose halves are
+/// collected into two `Vec`s
+///
+/// ```ignore
+/// let (xs, squares) = for_unzip! {
+///     for i in 0..4;
+///     (i, i * i)
+/// };
+/// assert_eq!(xs, [0, 1, 2, 3]);
+/// assert_eq!(squares, [0, 1, 4, 9]);
+/// ```
+///
+/// would expand to
+///
+/// ```ignore
+/// {
+///     let mut v = (Vec::new(), Vec::new());
+///     for i in 0..4 {
+///         Extend::extend(&mut v, std::iter::once((i, i * i)));
+///     }
+///     v
+/// }
+/// ```
+#[proc_macro]
+pub fn for_unzip(input: proc_macro::TokenStream) -> proc_macro::TokenStream {
+    let mut input = parse_macro_input!(input as ForCh);
+    let terminal = match split_terminal(&mut input.stmts) {
+        Ok(terminal) => terminal,
+        Err(err) => return err.to_compile_error().into(),
+    };
+
+    let acc = synthetic_ident("__acc");
+    let init = quote! { (::std::vec::Vec::new(), ::std::vec::Vec::new()) };
+    let push = syn::parse_quote! {
+        ::core::iter::Extend::extend(&mut #acc, ::core::iter::once(#terminal));
+    };
+    proc_macro::TokenStream::from(collect(input.stmts, &acc, init, push))
+}
+
+/// Like `for_vec!`, but finds the least and the greatest of the trailing
+/// expressions in one pass, as `Some((min, max))`, or `None` if there are none
+///
+/// ```ignore
+/// let extremes = for_minmax! {
+///     for x in [3, 1, 4, 1, 5];
+///     x * 2
+/// };
+/// assert_eq!(extremes, Some((2, 10)));
+/// ```
+///
+/// The values are compared with `<` and `>=`, so ties resolve as they do for
+/// `Iterator::min` and `Iterator::max`, and the first value is cloned to start
+/// both.
+#[proc_macro]
+pub fn for_minmax(input: proc_macro::TokenStream) -> proc_macro::TokenStream {
+    let mut input = parse_macro_input!(input as ForCh);
+    let terminal = match split_terminal(&mut input.stmts) {
+        Ok(terminal) => terminal,
+        Err(err) => return err.to_compile_error().into(),
+    };
+
+    let acc = synthetic_ident("__acc");
+    let value = synthetic_ident("__value");
+    let min = synthetic_ident("__min");
+    let max = synthetic_ident("__max");
+    let init = quote! { ::core::option::Option::None };
+    let push = syn::parse_quote! {
+        {
+            let #value = #terminal;
+            match #acc {
+                ::core::option::Option::None => {
+                    #acc = ::core::option::Option::Some((
+                        ::core::clone::Clone::clone(&#value),
+                        #value,
+                    ));
+                }
+                ::core::option::Option::Some((ref mut #min, ref mut #max)) => {
+                    if #value < *#min {
+                        *#min = #value;
+                    } else if #value >= *#max {
+                        *#max = #value;
+                    }
+                }
+            }
+        }
+    };
+    proc_macro::TokenStream::from(collect(input.stmts, &acc, init, push))
+}
+
+/// Like `for_vec!`, but collects the trailing expression into a `String`
+///
+/// The trailing expression may be anything a `String` can be extended with,
+/// e.g. `char`, `&str` or `String`.
+///
+/// ```ignore
+/// let s = for_string! {
+///     for c in "hello world".chars();
+///     if c != 'o';
+///     c
+/// };
+/// assert_eq!(s, "hell wrld");
+/// ```
+///
+/// would expand to
+///
+/// ```ignore
+/// {
+///     let mut s = String::new();
+///     for c in "hello world".chars() {
+///         if c != 'o' {
+///             Extend::extend(&mut s, std::iter::once(c));
+///         }
+///     }
+///     s
+/// }
+/// ```
+#[proc_macro]
+pub fn for_string(input: proc_macro::TokenStream) -> proc_macro::TokenStream {
+    let mut input = parse_macro_input!(input as ForCapacity);
+    let terminal = match split_terminal(&mut input.stmts) {
+        Ok(terminal) => terminal,
+        Err(err) => return err.to_compile_error().into(),
+    };
+
+    let acc = synthetic_ident("__acc");
+    let init = input.init(quote! { ::std::string::String });
+    let push = syn::parse_quote! {
+        ::core::iter::Extend::extend(&mut #acc, ::core::iter::once(#terminal));
+    };
+    proc_macro::TokenStream::from(collect(input.stmts, &acc, init, push))
+}
+
+/// Like `for_string!`, but the trailing item is a format string and its
+/// arguments, as accepted by `write!`
+///
+/// ```ignore
+/// let s = for_format! {
+///     for x in 0..3;
+///     "{}, ", x
+/// };
+/// assert_eq!(s, "0, 1, 2, ");
+/// ```
+///
+/// would expand to
+///
+/// ```ignore
+/// {
+///     let mut s = String::new();
+///     for x in 0..3 {
+///         write!(s, "{}, ", x).unwrap();
+///     }
+///     s
+/// }
+/// ```
+///
+/// A `Display` impl returning an error panics, and the panic is located at
+/// the format string.
+#[proc_macro]
+pub fn for_format(input: proc_macro::TokenStream) -> proc_macro::TokenStream {
+    let ForFormat { stmts, fmt } = parse_macro_input!(input as ForFormat);
+
+    let acc = synthetic_ident("__acc");
+    let init = quote! { ::std::string::String::new() };
+    // a failing `Display` impl panics at the format string
+    let span = fmt
+        .clone()
+        .into_iter()
+        .next()
+        .map_or_else(proc_macro2::Span::call_site, |token| token.span());
+    let expect = quote_spanned! {span=>
+        .expect("a formatting trait implementation returned an error")
+    };
+    let push = syn::parse_quote! {
+        ::core::fmt::Write::write_fmt(&mut #acc, ::core::format_args!(#fmt))#expect;
+    };
+    proc_macro::TokenStream::from(collect(stmts, &acc, init, push))
+}
+
+/// Keeps the elements of a `Vec` for which the chain's trailing expression is
+/// `true`, as `Vec::retain` does
+///
+/// The chain runs for each element, bound to the closure-like pattern, and the
+/// element is kept as soon as the trailing expression is `true`. An element
+/// for which every run stops at a guard, or yields `false`, is removed.
+///
+/// ```ignore
+/// let mut v = vec![3, 10, 12, 25];
+/// for_retain!(v, |&x| {
+///     if x > 5;
+///     let half = x / 2;
+///     half % 2 == 1
+/// });
+/// assert_eq!(v, [10]);
+/// ```
+///
+/// would expand to
+///
+/// ```ignore
+/// v.retain(|&x| 'for_ch: loop {
+///     if x > 5 {
+///         let half = x / 2;
+///         if half % 2 == 1 {
+///             break 'for_ch true;
+///         }
+///     }
+///     break false;
+/// });
+/// ```
+#[proc_macro]
+pub fn for_retain(input: proc_macro::TokenStream) -> proc_macro::TokenStream {
+    let ForRetain {
+        target,
+        pat,
+        mut stmts,
+        ..
+    } = parse_macro_input!(input as ForRetain);
+    let terminal = match split_terminal(&mut stmts) {
+        Ok(terminal) => terminal,
+        Err(err) => return err.to_compile_error().into(),
+    };
+
+    stmts.push(ForChItem::Stmt(syn::parse_quote! {
+        if #terminal {
+            break 'for_ch true;
+        }
+    }));
+    if let Err(err) = check_bare_jumps(&stmts) {
+        return err.to_compile_error().into();
+    }
+
+    let body = for_body(&stmts);
+    proc_macro::TokenStream::from(quote! {
+        (#target).retain(|#pat| 'for_ch: loop {
+            #body
+            break false;
+        })
+    })
+}
+
+/// Expands a collecting macro: `acc` starts as `init`, `push` runs
+/// innermost, and `acc` is the value of the whole expansion.
+fn collect(
+    mut stmts: Vec<ForChItem>,
+    acc: &proc_macro2::Ident,
+    init: proc_macro2::TokenStream,
+    push: syn::Stmt,
+) -> proc_macro2::TokenStream {
+    stmts.push(ForChItem::Stmt(push));
+    if let Err(err) = check_bare_jumps(&stmts) {
+        return err.to_compile_error();
+    }
+
+    let body = outer_loop(&stmts);
+    quote! {
+        {
+            let mut #acc = #init;
+            #body
+            #acc
+        }
+    }
+}
+
+/// Whether a clause before the first `for` has a bare `break` or `continue`,
+/// which only the outermost loop catches.
+fn jumps_out(stmts: &[ForChItem]) -> bool {
+    stmts
+        .iter()
+        .take_while(|item| !is_for_in(item))
+        .any(|item| is_else_continue(item) || check_bare_jumps(std::slice::from_ref(item)).is_err())
+}
+
+/// Rejects `break` and `continue` without a label in the clauses of a
+/// collecting macro, where they would silently leave whichever loop of the
+/// expansion is innermost.
+fn check_bare_jumps(stmts: &[ForChItem]) -> syn::Result<()> {
+    let mut jumps = BareJumps(None);
+    for item in stmts {
+        visit_clause(&mut jumps, item);
+    }
+
+    match jumps.0 {
+        Some(err) => Err(err),
+        None => Ok(()),
+    }
+}
+
+/// the first bare `break` or `continue` found
+struct BareJumps(Option<syn::Error>);
+
+/// Visits the code the caller wrote in a clause, except for `for` sources.
+fn visit_clause<'ast>(visitor: &mut impl Visit<'ast>, item: &'ast ForChItem) {
+    match item {
+        ForChItem::Stmt(s) => visitor.visit_stmt(s),
+        ForChItem::IfLet(IfLet { expr, .. })
+        | ForChItem::IfGuard(IfGuard { expr, .. })
+        | ForChItem::IfLetElseErr(IfLetElseErr { expr, .. }) => visitor.visit_expr(expr),
+        ForChItem::IfLetElse(IfLetElse { expr, block, .. })
+        | ForChItem::GuardLet(GuardLet { expr, block, .. }) => {
+            visitor.visit_expr(expr);
+            visitor.visit_block(block);
+        }
+        ForChItem::IfElse(if_else) => {
+            visitor.visit_expr(&if_else.expr);
+            visitor.visit_expr(&if_else.else_branch);
+        }
+        ForChItem::Do(do_block) => visitor.visit_block(&do_block.block),
+        ForChItem::Carry(carry) => visitor.visit_expr(&carry.init),
+        ForChItem::Cf(cf) => visitor.visit_expr(&cf.expr),
+        ForChItem::ForIn(_) => {}
+        ForChItem::Attributed(attributed) => visit_clause(visitor, &attributed.clause),
+    }
+}
+
+impl BareJumps {
+    fn found(&mut self, tokens: impl ToTokens, keyword: &str) {
+        if self.0.is_none() {
+            self.0 = Some(syn::Error::new_spanned(
+                tokens,
+                format!(
+                    "`{}` needs a label in a collecting macro, e.g. `break 'for_ch` to stop collecting",
+                    keyword
+                ),
+            ));
+        }
+    }
+}
+
+impl<'ast> Visit<'ast> for BareJumps {
+    fn visit_expr_break(&mut self, expr: &'ast syn::ExprBreak) {
+        if expr.label.is_none() {
+            self.found(expr.break_token, "break");
+        }
+        syn::visit::visit_expr_break(self, expr);
+    }
+
+    fn visit_expr_continue(&mut self, expr: &'ast syn::ExprContinue) {
+        if expr.label.is_none() {
+            self.found(expr.continue_token, "continue");
+        }
+    }
+
+    // jumps inside the caller's own loops, closures and items stay there
+    fn visit_expr_loop(&mut self, _: &'ast syn::ExprLoop) {}
+    fn visit_expr_while(&mut self, _: &'ast syn::ExprWhile) {}
+    fn visit_expr_for_loop(&mut self, _: &'ast syn::ExprForLoop) {}
+    fn visit_expr_closure(&mut self, _: &'ast syn::ExprClosure) {}
+    fn visit_expr_async(&mut self, _: &'ast syn::ExprAsync) {}
+    fn visit_item(&mut self, _: &'ast syn::Item) {}
+}
+
+/// the names bound by the patterns of `for` and `if let` clauses
+struct Bindings(Vec<syn::Ident>);
+
+impl Bindings {
+    fn bind_clause(&mut self, item: &ForChItem) {
+        match item {
+            ForChItem::ForIn(for_in) => {
+                for item in for_in.items.iter() {
+                    self.visit_pat(&item.pat);
+                }
+            }
+            ForChItem::IfLet(IfLet { pat, .. })
+            | ForChItem::IfLetElse(IfLetElse { pat, .. })
+            | ForChItem::IfLetElseErr(IfLetElseErr { pat, .. })
+            | ForChItem::GuardLet(GuardLet { pat, .. }) => self.visit_pat(pat),
+            // let-chains
+            ForChItem::IfGuard(IfGuard { expr, .. }) => {
+                for cond in conjuncts(expr) {
+                    if let syn::Expr::Let(syn::ExprLet { pat, .. }) = cond {
+                        self.visit_pat(pat);
+                    }
+                }
+            }
+            ForChItem::Attributed(attributed) => self.bind_clause(&attributed.clause),
+            _ => {}
+        }
+    }
+}
+
+impl<'ast> Visit<'ast> for Bindings {
+    fn visit_pat_ident(&mut self, pat: &'ast syn::PatIdent) {
+        let name = &pat.ident;
+        let constant = name.to_string().starts_with(char::is_uppercase);
+        if !constant && !self.0.contains(name) {
+            self.0.push(name.clone());
+        }
+        syn::visit::visit_pat_ident(self, pat);
+    }
+}
+
+/// How the clauses jump to the `'for_ch` label.
+fn for_ch_jumps(stmts: &[ForChItem]) -> ForChJumps {
+    let mut jumps = ForChJumps::default();
+    for item in stmts {
+        if is_cf(item) {
+            jumps.used = true;
+            jumps.valued = true;
+        }
+        visit_clause(&mut jumps, item);
+    }
+    jumps
+}
+
+#[derive(Default)]
+struct ForChJumps {
+    /// some `break 'for_ch` or `continue 'for_ch`
+    used: bool,
+    /// some `break 'for_ch value`
+    valued: bool,
+}
+
+impl<'ast> Visit<'ast> for ForChJumps {
+    fn visit_expr_break(&mut self, expr: &'ast syn::ExprBreak) {
+        if is_for_ch_label(&expr.label) {
+            self.used = true;
+            self.valued |= expr.expr.is_some();
+        }
+        syn::visit::visit_expr_break(self, expr);
+    }
+
+    fn visit_expr_continue(&mut self, expr: &'ast syn::ExprContinue) {
+        self.used |= is_for_ch_label(&expr.label);
+    }
+
+    // closures and items can't jump out of the chain
+    fn visit_expr_closure(&mut self, _: &'ast syn::ExprClosure) {}
+    fn visit_expr_async(&mut self, _: &'ast syn::ExprAsync) {}
+    fn visit_item(&mut self, _: &'ast syn::Item) {}
+}
+
+fn is_for_ch_label(label: &Option<syn::Lifetime>) -> bool {
+    matches!(label, Some(label) if label.ident == "for_ch")
+}
+
+/// Lowers the chain and wraps it in its outermost loop, labeled
+/// `'for_ch` only if a clause uses the label, so that nested expansions
+/// don't shadow it needlessly. A clause breaking out of it with a value
+/// makes `Default::default()` the value of a chain that runs to the end.
+/// Without any jump to it, the loop is left out. The hoisted `carry` clauses
+/// go ahead of the loop, so that `continue 'for_ch` doesn't reset them.
+fn outer_loop(stmts: &[ForChItem]) -> proc_macro2::TokenStream {
+    let carries = stmts
+        .iter()
+        .take_while(|item| matches!(item, ForChItem::Carry(_)))
+        .count();
+    let (carries, rest) = stmts.split_at(carries);
+    let body = for_body(rest);
+
+    let outer = match for_ch_jumps(stmts) {
+        ForChJumps { used: false, .. } if !jumps_out(stmts) => quote! {
+            {
+                #body
+            }
+        },
+        ForChJumps { used: false, .. } => quote! {
+            loop {
+                #body
+                break;
+            }
+        },
+        ForChJumps { valued: false, .. } => quote! {
+            'for_ch: loop {
+                #body
+                break;
+            }
+        },
+        ForChJumps { valued: true, .. } => quote! {
+            'for_ch: loop {
+                #body
+                break ::core::default::Default::default();
+            }
+        },
+    };
+    if carries.is_empty() {
+        return outer;
+    }
+    let carries = carries.iter().map(|item| for_item(item, quote! {}));
+    quote! {
+        {
+            #(#carries)*
+            #outer
+        }
+    }
+}
+
+/// Mints an identifier for a binding the expansion introduces, which can't
+/// clash with (or be named by) the caller's code.
+fn synthetic_ident(name: &str) -> proc_macro2::Ident {
+    proc_macro2::Ident::new(name, proc_macro2::Span::mixed_site())
+}
+
+/// Pops the trailing expression that a collecting macro accumulates.
+fn split_terminal(stmts: &mut Vec<ForChItem>) -> syn::Result<syn::Expr> {
+    pop_trailing_expr(stmts).ok_or_else(|| {
+        syn::Error::new(
+            proc_macro2::Span::call_site(),
+            "expected a trailing expression to collect",
+        )
+    })
+}
+
+/// Pops the last item if it is an expression without semicolon.
+fn pop_trailing_expr(stmts: &mut Vec<ForChItem>) -> Option<syn::Expr> {
+    match stmts.last() {
+        Some(ForChItem::Stmt(syn::Stmt::Expr(_))) => match stmts.pop() {
+            Some(ForChItem::Stmt(syn::Stmt::Expr(expr))) => Some(expr),
+            _ => unreachable!(),
+        },
+        _ => None,
+    }
+}
+
+/// for x in xs
+/// for? Some(x) in xs
+/// for await x in stream within deadline
+/// for x? in results
+struct ForInItem {
+    _for_tok: Token![for],
+    refutable: Option<Token![?]>,
+    await_tok: Option<Token![await]>,
+    pat: syn::Pat,
+    /// `?` applied to each element before it's matched against `pat`
+    try_tok: Option<Token![?]>,
+    _in_tok: Token![in],
+    iter: Source,
+    adapters: Vec<Adapter>,
+    /// or default: pads with `Default::default()` once exhausted
+    or_default: Option<kw::or>,
+    within: Option<syn::Expr>,
+}
+
+/// the `xs` of `for x in xs`
+#[allow(clippy::large_enum_variant)]
+enum Source {
+    Expr(syn::Expr),
+    #[cfg(feature = "itertools")]
+    ChunkBy(ChunkBy),
+    /// product xs, ys, zs
+    #[cfg(feature = "itertools")]
+    Product(Punctuated<syn::Expr, Token![,]>),
+    /// pairwise xs
+    #[cfg(feature = "itertools")]
+    Pairwise(syn::Expr),
+    Grid(Grid),
+}
+
+/// an iterator adapter after the source, e.g. `map |x| x + 1`
+enum Adapter {
+    Map(syn::Expr),
+    Filter(syn::Expr),
+    FlatMap(syn::Expr),
+    Step(syn::Expr),
+    Rev,
+    Take(syn::Expr),
+    Enumerate,
+    /// chars, bytes or lines of a `str`, right after the source
+    Str(syn::Ident),
+    /// windows n: of a slice, right after the source
+    Windows(syn::Expr),
+    /// if matches Some(_) | None
+    IfMatches(syn::Pat),
+    /// sorted, sorted_by |a, b| .., sorted_by_key |x| ..
+    Sorted(syn::Ident, Option<syn::Expr>),
+    /// dedup_by_key |x| ..
+    DedupByKey(syn::Expr),
+    /// zip_with ys via |x, y| ..
+    ZipWith(Box<syn::Expr>, syn::Expr),
+}
+
+/// for_ch::grid(rows, cols)
+struct Grid {
+    _grid_tok: kw::grid,
+    rows: syn::Expr,
+    cols: syn::Expr,
+}
+
+/// chunk_by(|x| key, xs)
+#[cfg(feature = "itertools")]
+struct ChunkBy {
+    _chunk_by_tok: kw::chunk_by,
+    key: syn::Expr,
+    iter: syn::Expr,
+}
+
+/// 'label: for x in xs | for y in ys | for z in zs ...;
+struct ForIn {
+    label: Option<syn::Label>,
+    /// flat for ..: zips into one flat tuple instead of nested pairs
+    flat: Option<kw::flat>,
+    zip: Zip,
+    items: Punctuated<ForInItem, Token![,]>,
+    _semi_tok: Token![;],
+    /// the binding of the loop's index, when the chain uses `$i`
+    index: Option<proc_macro2::Ident>,
+}
+
+/// repeat n times;
+struct Repeat {
+    _repeat_tok: kw::repeat,
+    count: syn::Expr,
+    _times_tok: kw::times,
+    _semi_tok: Token![;],
+}
+
+/// tee (a, b) = xs;
+struct Tee {
+    tee_tok: kw::tee,
+    _paren_tok: syn::token::Paren,
+    fst: syn::Ident,
+    _comma_tok: Token![,],
+    snd: syn::Ident,
+    _eq_tok: Token![=],
+    expr: syn::Expr,
+    _semi_tok: Token![;],
+}
+
+/// how the items of a `for` line are zipped
+#[derive(Clone, Copy)]
+enum Zip {
+    /// stops with the shortest item
+    Shortest,
+    /// zip_eq for ..: panics unless all items have the same length
+    Eq,
+    /// zip_checked for ..: checks the `ExactSizeIterator` lengths up front
+    Checked,
+}
+
+/// if let Some(x) = option;
+struct IfLet {
+    _if_tok: Token![if],
+    _let_tok: Token![let],
+    pat: syn::Pat,
+    _eq_tok: Token![=],
+    expr: syn::Expr,
+    _semi_tok: Token![;],
+}
+
+/// if expr;
+struct IfGuard {
+    _if_tok: Token![if],
+    expr: syn::Expr,
+    _semi_tok: Token![;],
+}
+
+/// if let Some(x) = option if cond;
+struct IfLetIf {
+    _if_tok: Token![if],
+    let_tok: Token![let],
+    pat: syn::Pat,
+    eq_tok: Token![=],
+    expr: syn::Expr,
+    _if2_tok: Token![if],
+    cond: syn::Expr,
+    _semi_tok: Token![;],
+}
+
+/// if x is 1 | 2;
+struct IfIs {
+    _if_tok: Token![if],
+    expr: syn::Expr,
+    _is_tok: kw::is,
+    pat: syn::Pat,
+    _semi_tok: Token![;],
+}
+
+/// if x in xs;
+struct IfIn {
+    _if_tok: Token![if],
+    value: syn::Expr,
+    _in_tok: Token![in],
+    container: syn::Expr,
+    _semi_tok: Token![;],
+}
+
+/// unless cond;
+struct Unless {
+    _unless_tok: kw::unless,
+    expr: syn::Expr,
+    _semi_tok: Token![;],
+}
+
+/// if let Ok(x) = result else err;
+struct IfLetElseErr {
+    _if_tok: Token![if],
+    _let_tok: Token![let],
+    pat: syn::Pat,
+    _eq_tok: Token![=],
+    expr: syn::Expr,
+    _else_tok: Token![else],
+    err_tok: kw::err,
+    _semi_tok: Token![;],
+}
+
+/// if let Some(x) = option else { stmt };
+struct IfLetElse {
+    _if_tok: Token![if],
+    _let_tok: Token![let],
+    pat: syn::Pat,
+    _eq_tok: Token![=],
+    expr: syn::Expr,
+    _else_tok: Token![else],
+    block: syn::Block,
+    _semi_tok: Token![;],
+}
+
+/// if expr else { stmt };
+/// if expr else if expr2 { stmt } else { stmt };
+struct IfElse {
+    _if_tok: Token![if],
+    expr: syn::Expr,
+    _else_tok: Token![else],
+    /// else continue { .. }: continues the innermost loop after the block
+    continue_tok: Option<Token![continue]>,
+    /// either a block or an `if` chain
+    else_branch: syn::Expr,
+    _semi_tok: Token![;],
+}
+
+enum ForChItem {
+    Stmt(syn::Stmt),
+    IfLet(IfLet),
+    IfGuard(IfGuard),
+    IfLetElse(IfLetElse),
+    IfLetElseErr(IfLetElseErr),
+    IfElse(IfElse),
+    GuardLet(GuardLet),
+    Do(DoBlock),
+    Carry(Carry),
+    Cf(Cf),
+    ForIn(ForIn),
+    Attributed(Attributed),
+}
+
+struct ForCh {
+    stmts: Vec<ForChItem>,
+}
+
+/// (guard)? let Some(x) = option else { diverge };
+struct GuardLet {
+    _guard_tok: Option<kw::guard>,
+    _let_tok: Token![let],
+    pat: syn::Pat,
+    _eq_tok: Token![=],
+    expr: syn::Expr,
+    _else_tok: Token![else],
+    block: syn::Block,
+    _semi_tok: Token![;],
+}
+
+/// do { stmt; stmt }
+struct DoBlock {
+    _do_tok: Token![do],
+    block: syn::Block,
+    _semi_tok: Option<Token![;]>,
+}
+
+/// carry best: i32 = 0;
+struct Carry {
+    _carry_tok: kw::carry,
+    name: syn::Ident,
+    ty: Option<(Token![:], syn::Type)>,
+    _eq_tok: Token![=],
+    init: syn::Expr,
+    _semi_tok: Token![;],
+}
+
+/// cf compute(x);
+struct Cf {
+    _cf_tok: kw::cf,
+    expr: syn::Expr,
+    _semi_tok: Token![;],
+}
+
+/// #[attr] clause;
+struct Attributed {
+    /// predicates of `#[cfg(..)]` attributes
+    cfgs: Vec<proc_macro2::TokenStream>,
+    attrs: Vec<syn::Attribute>,
+    clause: Box<ForChItem>,
+}
+
+/// move { ... }
+struct ForStream {
+    move_tok: Option<Token![move]>,
+    stmts: Vec<ForChItem>,
+}
+
+/// v, |x| { ... }
+struct ForRetain {
+    target: syn::Expr,
+    _comma_tok: Token![,],
+    _or1_tok: Token![|],
+    pat: syn::Pat,
+    _or2_tok: Token![|],
+    stmts: Vec<ForChItem>,
+}
+
+/// k, &mut rng, { ... }
+struct ForSample {
+    k: syn::Expr,
+    rng: Option<syn::Expr>,
+    stmts: Vec<ForChItem>,
+}
+
+/// size, { for w in xs; ... }
+struct ForWindows {
+    size: syn::Expr,
+    _comma_tok: Token![,],
+    stmts: Vec<ForChItem>,
+}
+
+/// n, { ... }
+struct ForVecTake {
+    limit: syn::Expr,
+    _comma_tok: Token![,],
+    stmts: Vec<ForChItem>,
+}
+
+/// cap = n, { ... }
+/// ...
+struct ForCapacity {
+    cap: Option<syn::Expr>,
+    stmts: Vec<ForChItem>,
+}
+
+/// sink, { ... }
+struct ForPushInto {
+    sink: syn::Expr,
+    _comma_tok: Token![,],
+    stmts: Vec<ForChItem>,
+}
+
+/// Target, { ... }
+struct ForCollectWith {
+    target: syn::Type,
+    _comma_tok: Token![,],
+    stmts: Vec<ForChItem>,
+}
+
+/// init, acc, { ... }
+struct ForTryFold {
+    init: syn::Expr,
+    _comma_tok: Token![,],
+    acc: syn::Ident,
+    _acc_comma_tok: Token![,],
+    stmts: Vec<ForChItem>,
+}
+
+/// #![for_each] ...
+struct ForChLowered {
+    lowering: Option<Lowering>,
+    chain: ForCh,
+}
+
+/// how `for_ch!` lowers a chain that could be `Iterator::for_each`
+enum Lowering {
+    /// #![for_each]
+    ForEach(syn::Attribute),
+    /// #![for_loop]
+    ForLoop,
+}
+
+/// ...; "{}", args
+struct ForFormat {
+    stmts: Vec<ForChItem>,
+    fmt: proc_macro2::TokenStream,
+}
+
+impl Parse for ForInItem {
+    fn parse(input: ParseStream) -> syn::Result<Self> {
+        let _for_tok = input.parse()?;
+        let refutable = input.parse()?;
+        let await_tok: Option<Token![await]> = input.parse()?;
+        if let Some(await_tok) = &await_tok {
+            if cfg!(not(feature = "stream")) {
+                return Err(syn::Error::new_spanned(
+                    await_tok,
+                    "`for await` needs the `stream` feature of for_ch",
+                ));
+            }
+        }
+
+        let pat = parse_flat_pat(input)?;
+        let try_tok = input.parse()?;
+        let _in_tok = input.parse()?;
+        let iter = input.parse()?;
+
+        let mut adapters = vec![];
+        if input.peek(kw::by) {
+            let by_tok = input.parse::<kw::by>()?;
+            if !matches!(iter, Source::Expr(syn::Expr::Range(_))) {
+                return Err(syn::Error::new_spanned(
+                    by_tok,
+                    "`by` needs a range source, use `step` for other sources",
+                ));
+            }
+            adapters.push(Adapter::Step(input.parse()?));
+        }
+        while let Some(adapter) = Adapter::parse_opt(input)? {
+            if !adapters.is_empty() {
+                match &adapter {
+                    Adapter::Str(method) => {
+                        return Err(syn::Error::new_spanned(
+                            method,
+                            format!("`{}` must directly follow the source", method),
+                        ))
+                    }
+                    Adapter::Windows(size) => {
+                        return Err(syn::Error::new_spanned(
+                            size,
+                            "`windows` must directly follow the source",
+                        ))
+                    }
+                    _ => {}
+                }
+            }
+            adapters.push(adapter);
+        }
+
+        let or_default = if input.peek(kw::or) && input.peek2(kw::default) {
+            let or_tok = input.parse()?;
+            input.parse::<kw::default>()?;
+            Some(or_tok)
+        } else {
+            None
+        };
+
+        let within = if await_tok.is_some() && input.peek(kw::within) {
+            let within_tok = input.parse::<kw::within>()?;
+            if cfg!(not(feature = "tokio")) {
+                return Err(syn::Error::new_spanned(
+                    within_tok,
+                    "`within` needs the `tokio` feature of for_ch",
+                ));
+            }
+            Some(input.parse()?)
+        } else {
+            None
+        };
+
+        Ok(Self {
+            _for_tok,
+            refutable,
+            await_tok,
+            pat,
+            try_tok,
+            _in_tok,
+            iter,
+            adapters,
+            or_default,
+            within,
+        })
+    }
+}
+
+impl Adapter {
+    fn parse_opt(input: ParseStream) -> syn::Result<Option<Self>> {
+        if input.peek(kw::map) {
+            input.parse::<kw::map>()?;
+            return Ok(Some(Adapter::Map(input.parse()?)));
+        }
+
+        if input.peek(kw::filter) {
+            input.parse::<kw::filter>()?;
+            return Ok(Some(Adapter::Filter(input.parse()?)));
+        }
+
+        if input.peek(kw::flat_map) {
+            input.parse::<kw::flat_map>()?;
+            return Ok(Some(Adapter::FlatMap(input.parse()?)));
+        }
+
+        if input.peek(kw::step) {
+            input.parse::<kw::step>()?;
+            return Ok(Some(Adapter::Step(input.parse()?)));
+        }
+
+        if input.peek(kw::rev) {
+            input.parse::<kw::rev>()?;
+            return Ok(Some(Adapter::Rev));
+        }
+
+        if input.peek(kw::enumerate) {
+            input.parse::<kw::enumerate>()?;
+            return Ok(Some(Adapter::Enumerate));
+        }
+
+        if input.peek(kw::chars) || input.peek(kw::bytes) || input.peek(kw::lines) {
+            return Ok(Some(Adapter::Str(input.call(syn::Ident::parse_any)?)));
+        }
+
+        if input.peek(kw::windows) {
+            input.parse::<kw::windows>()?;
+            return Ok(Some(Adapter::Windows(input.parse()?)));
+        }
+
+        if input.peek(kw::sorted) || input.peek(kw::sorted_by) || input.peek(kw::sorted_by_key) {
+            let method = input.call(syn::Ident::parse_any)?;
+            if cfg!(not(feature = "itertools")) {
+                return Err(syn::Error::new_spanned(
+                    method,
+                    "sorting needs the `itertools` feature of for_ch",
+                ));
+            }
+            let f = if method == "sorted" {
+                None
+            } else {
+                Some(input.parse()?)
+            };
+            return Ok(Some(Adapter::Sorted(method, f)));
+        }
+
+        if input.peek(kw::zip_with) {
+            input.parse::<kw::zip_with>()?;
+            let other = Box::new(input.parse()?);
+            input.parse::<kw::via>()?;
+            return Ok(Some(Adapter::ZipWith(other, input.parse()?)));
+        }
+
+        if input.peek(kw::dedup_by_key) {
+            input.parse::<kw::dedup_by_key>()?;
+            return Ok(Some(Adapter::DedupByKey(input.parse()?)));
+        }
+
+        if input.peek(kw::take) {
+            input.parse::<kw::take>()?;
+            return Ok(Some(Adapter::Take(input.parse()?)));
+        }
+
+        if input.peek(Token![if]) && input.peek2(kw::matches) {
+            input.parse::<Token![if]>()?;
+            input.parse::<kw::matches>()?;
+            return Ok(Some(Adapter::IfMatches(parse_multi_pat(input)?)));
+        }
+
+        Ok(None)
+    }
+
+    /// Applies the adapter to the iterator `iter`.
+    fn apply(&self, iter: proc_macro2::TokenStream) -> proc_macro2::TokenStream {
+        match self {
+            Adapter::Map(f) => quote! { #iter.map(#f) },
+            Adapter::Filter(f) => quote! { #iter.filter(#f) },
+            Adapter::FlatMap(f) => quote! { #iter.flat_map(#f) },
+            Adapter::Step(n) => quote! { #iter.step_by(#n) },
+            Adapter::Rev => quote! { #iter.rev() },
+            Adapter::Take(n) => quote! { #iter.take(#n) },
+            Adapter::Enumerate => quote! { #iter.enumerate() },
+            Adapter::Str(_) | Adapter::Windows(_) => {
+                unreachable!("`str` and slice adapters start the pipeline")
+            }
+            Adapter::IfMatches(pat) => {
+                let item = synthetic_ident("__item");
+                quote! { #iter.filter(|#item| ::core::matches!(#item, #pat)) }
+            }
+            Adapter::Sorted(method, f) => quote! { ::itertools::Itertools::#method(#iter, #f) },
+            Adapter::DedupByKey(f) => {
+                let it = synthetic_ident("__iter");
+                let last = synthetic_ident("__last");
+                let item = synthetic_ident("__item");
+                let next = synthetic_ident("__next");
+                let fresh = synthetic_ident("__fresh");
+                // a closure written out is inlined, as its parameter's type is
+                // only known inside the `filter`
+                let (bind_key, key) = match f {
+                    syn::Expr::Closure(closure) if closure.inputs.len() == 1 => {
+                        let pat = &closure.inputs[0];
+                        let body = &closure.body;
+                        (quote! {}, quote! { { let #pat = #item; #body } })
+                    }
+                    f => {
+                        let key = synthetic_ident("__key");
+                        (quote! { let mut #key = #f; }, quote! { #key(#item) })
+                    }
+                };
+                quote! {
+                    {
+                        let #it = #iter;
+                        #bind_key
+                        let mut #last = ::core::option::Option::None;
+                        ::core::iter::Iterator::filter(#it, move |#item| {
+                            let #next = ::core::option::Option::Some(#key);
+                            let #fresh = #last != #next;
+                            #last = #next;
+                            #fresh
+                        })
+                    }
+                }
+            }
+            Adapter::ZipWith(other, f) => match pair_closure(f) {
+                Some(f) => quote! { #iter.zip(#other).map(#f) },
+                None => {
+                    let zip = synthetic_ident("__zip");
+                    let f_ident = synthetic_ident("__f");
+                    let x = synthetic_ident("__x");
+                    let y = synthetic_ident("__y");
+                    quote! {
+                        {
+                            let #zip = #iter.zip(#other);
+                            let mut #f_ident = #f;
+                            #zip.map(move |(#x, #y)| #f_ident(#x, #y))
+                        }
+                    }
+                }
+            },
+        }
+    }
+}
+
+/// Rewrites a closure `|x, y| ..` to take the pair `|(x, y)| ..`, so that it
+/// can go straight into a `map` over a zip and see the types of `x` and `y`.
+fn pair_closure(f: &syn::Expr) -> Option<syn::ExprClosure> {
+    let closure = match f {
+        syn::Expr::Closure(closure) if closure.inputs.len() == 2 => closure,
+        _ => return None,
+    };
+
+    let mut pats = Punctuated::<syn::Pat, Token![,]>::new();
+    let mut tys = Punctuated::<syn::Type, Token![,]>::new();
+    let mut typed = false;
+    for input in &closure.inputs {
+        match input {
+            syn::Pat::Type(pat_type) => {
+                typed = true;
+                pats.push((*pat_type.pat).clone());
+                tys.push((*pat_type.ty).clone());
+            }
+            pat => {
+                pats.push(pat.clone());
+                tys.push(syn::parse_quote!(_));
+            }
+        }
+    }
+
+    let mut pat = syn::parse_quote!((#pats));
+    if typed {
+        pat = syn::Pat::Type(syn::PatType {
+            attrs: vec![],
+            pat: Box::new(pat),
+            colon_token: Default::default(),
+            ty: syn::parse_quote!((#tys)),
+        });
+    }
+    let mut closure = closure.clone();
+    closure.inputs = Punctuated::new();
+    closure.inputs.push(pat);
+    Some(closure)
+}
+
+/// Parses the pattern of a `for`, where `i, x` stands for the tuple `(i, x)`.
+fn parse_flat_pat(input: ParseStream) -> syn::Result<syn::Pat> {
+    let first = input.parse()?;
+    if !input.peek(Token![,]) {
+        return Ok(first);
+    }
+
+    let mut elems = Punctuated::new();
+    elems.push_value(first);
+    while input.peek(Token![,]) {
+        elems.push_punct(input.parse()?);
+        elems.push_value(input.parse()?);
+    }
+
+    Ok(syn::Pat::Tuple(syn::PatTuple {
+        attrs: vec![],
+        paren_token: Default::default(),
+        elems,
+    }))
+}
+
+/// Parses a pattern that may have `|` alternatives, with an optional leading `|`.
+fn parse_multi_pat(input: ParseStream) -> syn::Result<syn::Pat> {
+    let leading_vert: Option<Token![|]> = input.parse()?;
+    let mut cases = Punctuated::new();
+    cases.push_value(input.parse()?);
+    while input.peek(Token![|]) && !input.peek(Token![||]) {
+        cases.push_punct(input.parse()?);
+        cases.push_value(input.parse()?);
+    }
+
+    if leading_vert.is_none() && cases.len() == 1 {
+        return Ok(cases.into_iter().next().unwrap());
+    }
+
+    Ok(syn::Pat::Or(syn::PatOr {
+        attrs: vec![],
+        leading_vert,
+        cases,
+    }))
+}
+
+impl Parse for Source {
+    fn parse(input: ParseStream) -> syn::Result<Self> {
+        #[cfg(feature = "itertools")]
+        if input.peek(kw::chunk_by) && input.peek2(syn::token::Paren) {
+            return Ok(Source::ChunkBy(input.parse()?));
+        }
+
+        #[cfg(feature = "itertools")]
+        if input.peek(kw::product) && !continues_expr(input) {
+            input.parse::<kw::product>()?;
+            let mut sources = Punctuated::new();
+            sources.push_value(input.parse()?);
+            // a `, for` starts the next zipped item instead
+            while input.peek(Token![,]) && !input.peek2(Token![for]) {
+                sources.push_punct(input.parse()?);
+                sources.push_value(input.parse()?);
+            }
+            return Ok(Source::Product(sources));
+        }
+
+        #[cfg(feature = "itertools")]
+        if input.peek(kw::pairwise) && !continues_expr(input) {
+            input.parse::<kw::pairwise>()?;
+            return Ok(Source::Pairwise(input.parse()?));
+        }
+
+        if peek_grid(input) {
+            return Ok(Source::Grid(input.parse()?));
+        }
+
+        Ok(Source::Expr(input.parse()?))
+    }
+}
+
+/// Whether the source is `for_ch::grid(..)`, or `::for_ch::grid(..)`.
+fn peek_grid(input: ParseStream) -> bool {
+    let fork = input.fork();
+    fork.parse::<Option<Token![::]>>().is_ok()
+        && matches!(fork.parse::<syn::Ident>(), Ok(ident) if ident == "for_ch")
+        && fork.parse::<Token![::]>().is_ok()
+        && fork.peek(kw::grid)
+        && fork.peek2(syn::token::Paren)
+}
+
+impl Parse for Grid {
+    fn parse(input: ParseStream) -> syn::Result<Self> {
+        input.parse::<Option<Token![::]>>()?;
+        input.parse::<syn::Ident>()?;
+        input.parse::<Token![::]>()?;
+        let _grid_tok = input.parse()?;
+        let content;
+        syn::parenthesized!(content in input);
+        let rows = content.parse()?;
+        content.parse::<Token![,]>()?;
+        let cols = content.parse()?;
+        content.parse::<Option<Token![,]>>()?;
+
+        Ok(Self {
+            _grid_tok,
+            rows,
+            cols,
+        })
+    }
+}
+
+#[cfg(feature = "itertools")]
+impl Parse for ChunkBy {
+    fn parse(input: ParseStream) -> syn::Result<Self> {
+        let _chunk_by_tok = input.parse()?;
+        let content;
+        syn::parenthesized!(content in input);
+        let key = content.parse()?;
+        content.parse::<Token![,]>()?;
+        let iter = content.parse()?;
+        content.parse::<Option<Token![,]>>()?;
+
+        Ok(Self {
+            _chunk_by_tok,
+            key,
+            iter,
+        })
+    }
+}
+
+/// Whether the token after a contextual keyword makes it part of an ordinary
+/// expression, e.g. a variable `product` in `product.iter()`.
+fn continues_expr(input: ParseStream) -> bool {
+    input.peek2(Token![.])
+        || input.peek2(Token![::])
+        || input.peek2(Token![?])
+        || input.peek2(Token![;])
+        || input.peek2(Token![,])
+        || input.peek2(syn::token::Paren)
+        || input.peek2(syn::token::Bracket)
+        || {
+            let fork = input.fork();
+            fork.parse::<proc_macro2::TokenTree>().is_ok() && fork.is_empty()
+        }
+}
+
+impl ToTokens for Source {
+    fn to_tokens(&self, tokens: &mut proc_macro2::TokenStream) {
+        match self {
+            Source::Expr(expr) => expr.to_tokens(tokens),
+            // the `ChunkBy` temporary lives as long as the loop
+            #[cfg(feature = "itertools")]
+            Source::ChunkBy(chunk_by) => {
+                let key = &chunk_by.key;
+                let iter = &chunk_by.iter;
+                tokens.extend(quote! {
+                    &::itertools::Itertools::chunk_by(
+                        ::core::iter::IntoIterator::into_iter(#iter),
+                        #key,
+                    )
+                });
+            }
+            #[cfg(feature = "itertools")]
+            Source::Product(sources) => {
+                let sources = sources.iter();
+                tokens.extend(quote! { ::itertools::iproduct!(#(#sources),*) });
+            }
+            #[cfg(feature = "itertools")]
+            Source::Pairwise(iter) => tokens.extend(quote! {
+                ::itertools::Itertools::tuple_windows::<(_, _)>(
+                    ::core::iter::IntoIterator::into_iter(#iter),
+                )
+            }),
+            Source::Grid(Grid { rows, cols, .. }) => {
+                let (row, col) = (synthetic_ident("__row"), synthetic_ident("__col"));
+                let rows_ident = synthetic_ident("__rows");
+                let cols_ident = synthetic_ident("__cols");
+                tokens.extend(quote! {
+                    {
+                        let #rows_ident = #rows;
+                        let #cols_ident = #cols;
+                        ::core::iter::Iterator::flat_map(0..#rows_ident, move |#row| {
+                            ::core::iter::Iterator::map(0..#cols_ident, move |#col| (#row, #col))
+                        })
+                    }
+                });
+            }
+        }
+    }
+}
+
+impl Parse for ForIn {
+    fn parse(input: ParseStream) -> syn::Result<Self> {
+        let label = if input.peek(syn::Lifetime) && input.peek2(Token![:]) {
+            Some(input.parse()?)
+        } else {
+            None
+        };
+
+        let flat = if input.peek(kw::flat)
+            && (input.peek2(Token![for]) || input.peek2(kw::zip_eq) || input.peek2(kw::zip_checked))
+        {
+            Some(input.parse()?)
+        } else {
+            None
+        };
+
+        let zip = if input.peek(kw::zip_eq) && input.peek2(Token![for]) {
+            let zip_eq_tok = input.parse::<kw::zip_eq>()?;
+            if cfg!(not(feature = "itertools")) {
+                return Err(syn::Error::new_spanned(
+                    zip_eq_tok,
+                    "`zip_eq` needs the `itertools` feature of for_ch",
+                ));
+            }
+            Zip::Eq
+        } else if input.peek(kw::zip_checked) && input.peek2(Token![for]) {
+            input.parse::<kw::zip_checked>()?;
+            Zip::Checked
+        } else {
+            Zip::Shortest
+        };
+
+        let mut items = Punctuated::new();
+
+        // first item
+        items.push_value(input.parse()?);
+
+        // (| for_in_item)*
+        while !input.is_empty() && input.peek(Token![,]) && input.peek2(Token![for]) {
+            items.push_punct(input.parse()?);
+            items.push_value(input.parse()?);
+        }
+
+        for item in items.iter() {
+            let item: &ForInItem = item;
+            if let Some(await_tok) = &item.await_tok {
+                if items.len() > 1 {
+                    return Err(syn::Error::new_spanned(
+                        await_tok,
+                        "`for await` can't be zipped",
+                    ));
+                }
+                if !item.adapters.is_empty() {
+                    return Err(syn::Error::new_spanned(
+                        await_tok,
+                        "`for await` doesn't take adapters",
+                    ));
+                }
+            }
+            if let Some(or_tok) = &item.or_default {
+                match zip {
+                    Zip::Shortest => {}
+                    Zip::Eq => {
+                        return Err(syn::Error::new_spanned(
+                            or_tok,
+                            "`or default` can't be used with `zip_eq`",
+                        ))
+                    }
+                    Zip::Checked => {
+                        return Err(syn::Error::new_spanned(
+                            or_tok,
+                            "`or default` can't be used with `zip_checked`",
+                        ))
+                    }
+                }
+            }
+        }
+
+        if let Some(last) = items.last() {
+            if items.iter().all(|item| item.or_default.is_some()) {
+                return Err(syn::Error::new_spanned(
+                    last.or_default,
+                    "`or default` needs another item that isn't padded to end the loop",
+                ));
+            }
+        }
+
+        Ok(Self {
+            label,
+            flat,
+            zip,
+            items,
+            _semi_tok: input.parse()?,
+            index: None,
+        })
+    }
+}
+
+impl Parse for Repeat {
+    fn parse(input: ParseStream) -> syn::Result<Self> {
+        Ok(Self {
+            _repeat_tok: input.parse()?,
+            count: input.parse()?,
+            _times_tok: input.parse()?,
+            _semi_tok: input.parse()?,
+        })
+    }
+}
+
+impl Parse for Tee {
+    fn parse(input: ParseStream) -> syn::Result<Self> {
+        let content;
+        Ok(Self {
+            tee_tok: input.parse()?,
+            _paren_tok: syn::parenthesized!(content in input),
+            fst: content.parse()?,
+            _comma_tok: content.parse()?,
+            snd: content.parse()?,
+            _eq_tok: input.parse()?,
+            expr: input.parse()?,
+            _semi_tok: input.parse()?,
+        })
+    }
+}
+
+impl Tee {
+    /// `let (a, b) = Itertools::tee(IntoIterator::into_iter(xs));`
+    fn into_stmt(self) -> syn::Stmt {
+        let Self { fst, snd, expr, .. } = self;
+        syn::parse_quote! {
+            let (#fst, #snd) = ::itertools::Itertools::tee(
+                ::core::iter::IntoIterator::into_iter(#expr),
+            );
+        }
+    }
+}
+
+impl Repeat {
+    /// `for _ in 0..n;`
+    fn into_for_in(self) -> ForIn {
+        let count = self.count;
+        let mut items = Punctuated::new();
+        items.push(ForInItem {
+            _for_tok: Default::default(),
+            refutable: None,
+            await_tok: None,
+            pat: syn::parse_quote! { _ },
+            try_tok: None,
+            _in_tok: Default::default(),
+            iter: Source::Expr(syn::parse_quote! { 0..#count }),
+            adapters: vec![],
+            or_default: None,
+            within: None,
+        });
+        ForIn {
+            label: None,
+            flat: None,
+            zip: Zip::Shortest,
+            items,
+            _semi_tok: self._semi_tok,
+            index: None,
+        }
+    }
+}
+
+impl ToTokens for ForIn {
+    fn to_tokens(&self, tokens: &mut proc_macro2::TokenStream) {
+        let (pat, iter) = self.indexed_header();
+
+        self.label.to_tokens(tokens);
+        quote!(for).to_tokens(tokens);
+        pat.to_tokens(tokens);
+        quote!(in).to_tokens(tokens);
+        iter.to_tokens(tokens);
+    }
+}
+
+impl ForInItem {
+    /// The source with its adapters applied from left to right.
+    fn iter(&self) -> proc_macro2::TokenStream {
+        let iter = &self.iter;
+        if self.adapters.is_empty() && self.or_default.is_none() {
+            return quote! { #iter };
+        }
+
+        self.iter_arg()
+    }
+
+    /// Like `iter`, but for an argument position, where parentheses or braces
+    /// around the source are unneeded and would be linted as such.
+    fn iter_arg(&self) -> proc_macro2::TokenStream {
+        let iter = match &self.iter {
+            Source::Expr(syn::Expr::Paren(paren)) if paren.attrs.is_empty() => {
+                paren.expr.to_token_stream()
+            }
+            Source::Expr(syn::Expr::Block(block))
+                if block.attrs.is_empty() && block.label.is_none() =>
+            {
+                match block.block.stmts.as_slice() {
+                    [syn::Stmt::Expr(expr)] => expr.to_token_stream(),
+                    _ => block.to_token_stream(),
+                }
+            }
+            iter => iter.to_token_stream(),
+        };
+        if self.adapters.is_empty() && self.or_default.is_none() {
+            return iter;
+        }
+
+        let (iter, adapters) = match self.adapters.split_first() {
+            Some((Adapter::Str(method), adapters)) => (quote! { (#iter).#method() }, adapters),
+            Some((Adapter::Windows(size), adapters)) => {
+                (quote! { (#iter).windows(#size) }, adapters)
+            }
+            _ => (
+                quote! { ::core::iter::IntoIterator::into_iter(#iter) },
+                &self.adapters[..],
+            ),
+        };
+        let iter = adapters
+            .iter()
+            .fold(iter, |iter, adapter| adapter.apply(iter));
+        match self.or_default {
+            Some(_) => quote! {
+                #iter.chain(::core::iter::repeat_with(::core::default::Default::default))
+            },
+            None => iter,
+        }
+    }
+
+    /// What the loop binds: a refutable pattern, or one under `?`, is
+    /// matched inside the loop against a placeholder instead.
+    fn binding(&self, index: usize) -> proc_macro2::TokenStream {
+        if self.refutable.is_some() || self.try_tok.is_some() {
+            synthetic_ident(&format!("__item{}", index)).into_token_stream()
+        } else {
+            self.pat.to_token_stream()
+        }
+    }
+}
+
+impl ForIn {
+    fn is_await(&self) -> bool {
+        self.items.iter().any(|item| item.await_tok.is_some())
+    }
+
+    /// The loop's pattern and iterator, enumerated when the chain uses `$i`.
+    fn indexed_header(&self) -> (proc_macro2::TokenStream, proc_macro2::TokenStream) {
+        let (pat, iter) = self.header();
+        match &self.index {
+            Some(index) => (
+                quote! { (#index, #pat) },
+                quote! {
+                    ::core::iter::Iterator::enumerate(::core::iter::IntoIterator::into_iter(#iter))
+                },
+            ),
+            None => (pat, iter),
+        }
+    }
+
+    /// Makes `$i` refer to this loop's index in `rest`.
+    fn with_index(&self, rest: proc_macro2::TokenStream) -> proc_macro2::TokenStream {
+        match &self.index {
+            Some(index) => {
+                let innermost = synthetic_ident("__index");
+                quote! {
+                    #[allow(unused_variables)]
+                    let #innermost = #index;
+                    #rest
+                }
+            }
+            None => rest,
+        }
+    }
+
+    /// The loop's pattern and iterator.
+    fn header(&self) -> (proc_macro2::TokenStream, proc_macro2::TokenStream) {
+        if self.items.len() == 1 {
+            let item = &self.items[0];
+            return (item.binding(0), item.iter());
+        }
+
+        let (pat, iter) = for_in_zippings(self.items.iter().enumerate(), self.zip);
+        if self.flat.is_none() {
+            return (pat, iter);
+        }
+
+        // `(a, (b, c))` to `(a, b, c)`
+        let elems: Vec<_> = (0..self.items.len())
+            .map(|index| synthetic_ident(&format!("__elem{}", index)))
+            .collect();
+        let nested = elems
+            .iter()
+            .rev()
+            .map(|elem| quote! { #elem })
+            .reduce(|snd, fst| quote! { (#fst, #snd) });
+        let pats = self
+            .items
+            .iter()
+            .enumerate()
+            .map(|(index, item)| item.binding(index));
+        (
+            quote! { (#(#pats,)*) },
+            quote! { ::core::iter::Iterator::map(#iter, |#nested| (#(#elems,)*)) },
+        )
+    }
+
+    /// The placeholders of the items whose patterns are matched inside the
+    /// loop, and those items, innermost first.
+    fn placeholders(&self) -> impl Iterator<Item = (proc_macro2::TokenStream, &ForInItem)> {
+        self.items
+            .iter()
+            .enumerate()
+            .rev()
+            .filter(|(_, item)| item.refutable.is_some() || item.try_tok.is_some())
+            .map(|(index, item)| (item.binding(index), item))
+    }
+}
+
+fn for_in_zippings<'a>(
+    mut items: impl Iterator<Item = (usize, &'a ForInItem)>,
+    zip: Zip,
+) -> (proc_macro2::TokenStream, proc_macro2::TokenStream) {
+    let (fst_pat, fst_iter) = if let Some((index, fst)) = items.next() {
+        (fst.binding(index), fst.iter_arg())
+    } else {
+        return Default::default();
+    };
+
+    let (snd_pat, snd_iter) = for_in_zippings(items, zip);
+    if snd_pat.is_empty() || snd_iter.is_empty() {
+        return (quote! { #fst_pat }, quote! { #fst_iter });
+    }
+
+    let fst_iter = quote! { ::core::iter::IntoIterator::into_iter(#fst_iter) };
+    let iter = match zip {
+        Zip::Shortest => quote! { #fst_iter.zip(#snd_iter) },
+        Zip::Eq => quote! { ::itertools::Itertools::zip_eq(#fst_iter, #snd_iter) },
+        Zip::Checked => {
+            let fst = synthetic_ident("__fst");
+            let snd = synthetic_ident("__snd");
+            quote! {
+                {
+                    let #fst = #fst_iter;
+                    let #snd = ::core::iter::IntoIterator::into_iter(#snd_iter);
+                    ::core::assert_eq!(
+                        ::core::iter::ExactSizeIterator::len(&#fst),
+                        ::core::iter::ExactSizeIterator::len(&#snd),
+                        "zip_checked: the zipped items have different lengths",
+                    );
+                    ::core::iter::Iterator::zip(#fst, #snd)
+                }
+            }
+        }
+    };
+    (quote! { (#fst_pat, #snd_pat) }, iter)
+}
+
+impl Parse for IfLet {
+    fn parse(input: ParseStream) -> syn::Result<Self> {
+        Ok(Self {
+            _if_tok: input.parse()?,
+            _let_tok: input.parse()?,
+            pat: parse_multi_pat(input)?,
+            _eq_tok: input.parse()?,
+            expr: input.parse()?,
+            _semi_tok: input.parse()?,
+        })
+    }
+}
+
+impl Parse for IfGuard {
+    fn parse(input: ParseStream) -> syn::Result<Self> {
+        Ok(Self {
+            _if_tok: input.parse()?,
+            expr: input.parse()?,
+            _semi_tok: input.parse()?,
+        })
+    }
+}
+
+impl Parse for IfLetIf {
+    fn parse(input: ParseStream) -> syn::Result<Self> {
+        Ok(Self {
+            _if_tok: input.parse()?,
+            let_tok: input.parse()?,
+            pat: parse_multi_pat(input)?,
+            eq_tok: input.parse()?,
+            expr: input.parse()?,
+            _if2_tok: input.parse()?,
+            cond: input.parse()?,
+            _semi_tok: input.parse()?,
+        })
+    }
+}
+
+impl IfLetIf {
+    /// the let-chain `if let Some(x) = option && cond;`
+    fn into_guard(self) -> IfGuard {
+        let scrutinee = syn::Expr::Let(syn::ExprLet {
+            attrs: vec![],
+            let_token: self.let_tok,
+            pat: self.pat,
+            eq_token: self.eq_tok,
+            expr: Box::new(self.expr),
+        });
+        IfGuard {
+            _if_tok: self._if_tok,
+            expr: syn::Expr::Binary(syn::ExprBinary {
+                attrs: vec![],
+                left: Box::new(scrutinee),
+                op: syn::BinOp::And(Default::default()),
+                right: Box::new(self.cond),
+            }),
+            _semi_tok: self._semi_tok,
+        }
+    }
+}
+
+impl Parse for IfIs {
+    fn parse(input: ParseStream) -> syn::Result<Self> {
         Ok(Self {
-            label,
-            items,
+            _if_tok: input.parse()?,
+            expr: input.parse()?,
+            _is_tok: input.parse()?,
+            pat: parse_multi_pat(input)?,
             _semi_tok: input.parse()?,
         })
     }
 }
 
-impl ToTokens for ForIn {
+impl IfIs {
+    /// `if matches!(x, 1 | 2);`
+    fn into_guard(self) -> IfGuard {
+        let IfIs {
+            _if_tok,
+            expr,
+            pat,
+            _semi_tok,
+            ..
+        } = self;
+        IfGuard {
+            _if_tok,
+            expr: syn::parse_quote! { ::core::matches!(#expr, #pat) },
+            _semi_tok,
+        }
+    }
+}
+
+impl Parse for IfIn {
+    fn parse(input: ParseStream) -> syn::Result<Self> {
+        Ok(Self {
+            _if_tok: input.parse()?,
+            value: input.parse()?,
+            _in_tok: input.parse()?,
+            container: input.parse()?,
+            _semi_tok: input.parse()?,
+        })
+    }
+}
+
+impl IfIn {
+    /// `if (xs).contains(&x);`
+    fn into_guard(self) -> IfGuard {
+        let IfIn {
+            _if_tok,
+            value,
+            container,
+            _semi_tok,
+            ..
+        } = self;
+        IfGuard {
+            _if_tok,
+            expr: syn::parse_quote! { (#container).contains(&#value) },
+            _semi_tok,
+        }
+    }
+}
+
+impl Parse for Unless {
+    fn parse(input: ParseStream) -> syn::Result<Self> {
+        let _unless_tok = input.parse()?;
+        let expr = input.parse()?;
+        if let syn::Expr::Let(expr) = &expr {
+            return Err(syn::Error::new_spanned(expr, "`unless` can't bind"));
+        }
+        Ok(Self {
+            _unless_tok,
+            expr,
+            _semi_tok: input.parse()?,
+        })
+    }
+}
+
+impl Unless {
+    /// `if !(cond);`
+    fn into_guard(self) -> IfGuard {
+        let expr = self.expr;
+        IfGuard {
+            _if_tok: Default::default(),
+            expr: syn::parse_quote! { !(#expr) },
+            _semi_tok: self._semi_tok,
+        }
+    }
+}
+
+impl Parse for IfLetElseErr {
+    fn parse(input: ParseStream) -> syn::Result<Self> {
+        Ok(Self {
+            _if_tok: input.parse()?,
+            _let_tok: input.parse()?,
+            pat: parse_multi_pat(input)?,
+            _eq_tok: input.parse()?,
+            expr: input.parse()?,
+            _else_tok: input.parse()?,
+            err_tok: input.parse()?,
+            _semi_tok: input.parse()?,
+        })
+    }
+}
+
+impl Parse for IfLetElse {
+    fn parse(input: ParseStream) -> syn::Result<Self> {
+        Ok(Self {
+            _if_tok: input.parse()?,
+            _let_tok: input.parse()?,
+            pat: parse_multi_pat(input)?,
+            _eq_tok: input.parse()?,
+            expr: input.parse()?,
+            _else_tok: input.parse()?,
+            block: input.parse()?,
+            _semi_tok: input.parse()?,
+        })
+    }
+}
+
+impl Parse for IfElse {
+    fn parse(input: ParseStream) -> syn::Result<Self> {
+        let _if_tok = input.parse()?;
+        let expr = input.parse()?;
+        let _else_tok = input.parse()?;
+        let continue_tok: Option<Token![continue]> = input.parse()?;
+        Ok(Self {
+            _if_tok,
+            expr,
+            _else_tok,
+            continue_tok,
+            else_branch: if continue_tok.is_none() && input.peek(Token![if]) {
+                syn::Expr::If(input.parse()?)
+            } else {
+                syn::Expr::Block(input.parse()?)
+            },
+            _semi_tok: input.parse()?,
+        })
+    }
+}
+
+impl Parse for GuardLet {
+    fn parse(input: ParseStream) -> syn::Result<Self> {
+        Ok(Self {
+            _guard_tok: input.parse()?,
+            _let_tok: input.parse()?,
+            pat: parse_multi_pat(input)?,
+            _eq_tok: input.parse()?,
+            expr: input.parse()?,
+            _else_tok: input.parse()?,
+            block: input.parse()?,
+            _semi_tok: input.parse()?,
+        })
+    }
+}
+
+impl Parse for Carry {
+    fn parse(input: ParseStream) -> syn::Result<Self> {
+        let _carry_tok = input.parse()?;
+        let name = input.parse()?;
+        let ty = if input.peek(Token![:]) {
+            Some((input.parse()?, input.parse()?))
+        } else {
+            None
+        };
+        Ok(Self {
+            _carry_tok,
+            name,
+            ty,
+            _eq_tok: input.parse()?,
+            init: input.parse()?,
+            _semi_tok: input.parse()?,
+        })
+    }
+}
+
+impl ToTokens for Carry {
     fn to_tokens(&self, tokens: &mut proc_macro2::TokenStream) {
-        let (pat, iter) = for_in_zippings(self.items.iter());
+        let name = &self.name;
+        let init = &self.init;
+        let ty = self.ty.as_ref().map(|(colon, ty)| quote! { #colon #ty });
+        tokens.extend(quote! {
+            let mut #name #ty = #init;
+        });
+    }
+}
 
-        self.label.to_tokens(tokens);
-        quote!(for).to_tokens(tokens);
-        pat.to_tokens(tokens);
-        quote!(in).to_tokens(tokens);
-        iter.to_tokens(tokens);
+impl Parse for Cf {
+    fn parse(input: ParseStream) -> syn::Result<Self> {
+        Ok(Self {
+            _cf_tok: input.parse()?,
+            expr: input.parse()?,
+            _semi_tok: input.parse()?,
+        })
+    }
+}
+
+impl Parse for DoBlock {
+    fn parse(input: ParseStream) -> syn::Result<Self> {
+        Ok(Self {
+            _do_tok: input.parse()?,
+            block: input.parse()?,
+            _semi_tok: input.parse()?,
+        })
+    }
+}
+
+impl Parse for ForChItem {
+    fn parse(input: ParseStream) -> syn::Result<Self> {
+        // attributes on a clause, attributes on a statement are left to `syn::Stmt`
+        let fork = input.fork();
+        let attrs = fork.call(syn::Attribute::parse_outer)?;
+        if !attrs.is_empty() {
+            if let Some(clause) = parse_clause(&fork)? {
+                input.advance_to(&fork);
+                return Ok(ForChItem::Attributed(Attributed::new(attrs, clause)?));
+            }
+        }
+
+        if let Some(clause) = parse_clause(input)? {
+            return Ok(clause);
+        }
+
+        // trailing expression without semicolon
+        let fork = input.fork();
+        if let Ok(expr) = fork.parse::<syn::Expr>() {
+            if fork.is_empty() {
+                input.advance_to(&fork);
+                return Ok(ForChItem::Stmt(syn::Stmt::Expr(expr)));
+            }
+        }
+
+        Ok(ForChItem::Stmt(input.parse()?))
+    }
+}
+
+/// Parses any item except a plain statement.
+fn parse_clause(input: ParseStream) -> syn::Result<Option<ForChItem>> {
+    let fork = input.fork();
+    if let Ok(if_guard) = fork.parse::<IfGuard>() {
+        input.advance_to(&fork);
+        return Ok(Some(ForChItem::IfGuard(if_guard)));
+    }
+
+    let fork = input.fork();
+    if let Ok(if_let_if) = fork.parse::<IfLetIf>() {
+        input.advance_to(&fork);
+        return Ok(Some(ForChItem::IfGuard(if_let_if.into_guard())));
+    }
+
+    if input.peek(kw::unless) {
+        let fork = input.fork();
+        if let Ok(unless) = fork.parse::<Unless>() {
+            input.advance_to(&fork);
+            return Ok(Some(ForChItem::IfGuard(unless.into_guard())));
+        }
+    }
+
+    let fork = input.fork();
+    if let Ok(if_is) = fork.parse::<IfIs>() {
+        input.advance_to(&fork);
+        return Ok(Some(ForChItem::IfGuard(if_is.into_guard())));
+    }
+
+    let fork = input.fork();
+    if let Ok(if_in) = fork.parse::<IfIn>() {
+        input.advance_to(&fork);
+        return Ok(Some(ForChItem::IfGuard(if_in.into_guard())));
+    }
+
+    let fork = input.fork();
+    if let Ok(if_let) = fork.parse::<IfLet>() {
+        input.advance_to(&fork);
+        return Ok(Some(ForChItem::IfLet(if_let)));
+    }
+
+    let fork = input.fork();
+    if let Ok(if_else) = fork.parse::<IfElse>() {
+        input.advance_to(&fork);
+        return Ok(Some(ForChItem::IfElse(if_else)));
+    }
+
+    let fork = input.fork();
+    if let Ok(if_let_else) = fork.parse::<IfLetElse>() {
+        input.advance_to(&fork);
+        return Ok(Some(ForChItem::IfLetElse(if_let_else)));
+    }
+
+    let fork = input.fork();
+    if let Ok(if_let_else_err) = fork.parse::<IfLetElseErr>() {
+        input.advance_to(&fork);
+        return Ok(Some(ForChItem::IfLetElseErr(if_let_else_err)));
+    }
+
+    if input.peek(kw::guard) && input.peek2(Token![let]) {
+        return Ok(Some(ForChItem::GuardLet(input.parse()?)));
+    }
+
+    // a plain `let` unless it has an `else`
+    let fork = input.fork();
+    if let Ok(guard_let) = fork.parse::<GuardLet>() {
+        input.advance_to(&fork);
+        return Ok(Some(ForChItem::GuardLet(guard_let)));
+    }
+
+    if input.peek(Token![do]) && input.peek2(syn::token::Brace) {
+        return Ok(Some(ForChItem::Do(input.parse()?)));
+    }
+
+    if input.peek(kw::carry) && input.peek2(syn::Ident) {
+        return Ok(Some(ForChItem::Carry(input.parse()?)));
+    }
+
+    if input.peek(kw::cf) && !continues_expr(input) {
+        let fork = input.fork();
+        if let Ok(cf) = fork.parse::<Cf>() {
+            input.advance_to(&fork);
+            return Ok(Some(ForChItem::Cf(cf)));
+        }
+    }
+
+    let fork = input.fork();
+    if let Ok(for_in) = fork.parse::<ForIn>() {
+        input.advance_to(&fork);
+        return Ok(Some(ForChItem::ForIn(for_in)));
+    }
+
+    if input.peek(kw::tee) && input.peek2(syn::token::Paren) {
+        let fork = input.fork();
+        if let Ok(tee) = fork.parse::<Tee>() {
+            if cfg!(not(feature = "itertools")) {
+                return Err(syn::Error::new_spanned(
+                    tee.tee_tok,
+                    "`tee` needs the `itertools` feature of for_ch",
+                ));
+            }
+            input.advance_to(&fork);
+            return Ok(Some(ForChItem::Stmt(tee.into_stmt())));
+        }
+    }
+
+    let fork = input.fork();
+    if let Ok(repeat) = fork.parse::<Repeat>() {
+        input.advance_to(&fork);
+        return Ok(Some(ForChItem::ForIn(repeat.into_for_in())));
+    }
+
+    Ok(None)
+}
+
+impl Attributed {
+    fn new(attrs: Vec<syn::Attribute>, clause: ForChItem) -> syn::Result<Self> {
+        // a hoisted `carry` would leave its attributes' scope behind
+        if let ForChItem::Carry(carry) = &clause {
+            return Err(syn::Error::new_spanned(
+                &carry.name,
+                "`carry` clauses can't have attributes",
+            ));
+        }
+
+        let mut cfgs = vec![];
+        let mut rest = vec![];
+        for attr in attrs {
+            if attr.path.is_ident("cfg") {
+                cfgs.push(attr.parse_args()?);
+            } else {
+                rest.push(attr);
+            }
+        }
+
+        Ok(Self {
+            cfgs,
+            attrs: rest,
+            clause: Box::new(clause),
+        })
+    }
+}
+
+impl Parse for ForCh {
+    fn parse(input: ParseStream) -> syn::Result<Self> {
+        let (tokens, indexed) = expand_indices(input.parse()?);
+        let mut stmts = syn::parse::Parser::parse2(
+            |input: ParseStream| {
+                let mut stmts = vec![];
+                while !input.is_empty() {
+                    stmts.push(input.parse()?);
+                }
+                Ok(stmts)
+            },
+            tokens,
+        )?;
+        if indexed {
+            number_loops(&mut stmts, &mut 0);
+        }
+        hoist_carries(&mut stmts);
+
+        Ok(Self { stmts })
+    }
+}
+
+/// Rewrites `$i` into the index of the innermost loop, and `$i0`, `$i1`, ..
+/// into the index of the first, second, .. loop of the chain. Returns whether
+/// there were any.
+fn expand_indices(tokens: proc_macro2::TokenStream) -> (proc_macro2::TokenStream, bool) {
+    use proc_macro2::TokenTree;
+
+    let mut indexed = false;
+    let mut output = vec![];
+    let mut tokens = tokens.into_iter().peekable();
+    while let Some(token) = tokens.next() {
+        match token {
+            TokenTree::Punct(dollar) if dollar.as_char() == '$' => {
+                let index = match tokens.peek() {
+                    Some(TokenTree::Ident(ident)) => {
+                        let name = ident.to_string();
+                        let digits = name.strip_prefix('i').unwrap_or("x");
+                        if digits.chars().all(|c| c.is_ascii_digit()) {
+                            let span = proc_macro2::Span::mixed_site().located_at(ident.span());
+                            Some(proc_macro2::Ident::new(&format!("__index{}", digits), span))
+                        } else {
+                            None
+                        }
+                    }
+                    _ => None,
+                };
+                match index {
+                    Some(index) => {
+                        tokens.next();
+                        indexed = true;
+                        output.push(TokenTree::Ident(index));
+                    }
+                    None => output.push(TokenTree::Punct(dollar)),
+                }
+            }
+            TokenTree::Group(group) => {
+                let (stream, group_indexed) = expand_indices(group.stream());
+                indexed |= group_indexed;
+                let mut expanded = proc_macro2::Group::new(group.delimiter(), stream);
+                expanded.set_span(group.span());
+                output.push(TokenTree::Group(expanded));
+            }
+            token => output.push(token),
+        }
+    }
+
+    (output.into_iter().collect(), indexed)
+}
+
+/// Moves the `carry` clauses ahead of the whole chain, keeping their order, so
+/// that each is initialized once.
+fn hoist_carries(stmts: &mut [ForChItem]) {
+    stmts.sort_by_key(|item| !matches!(item, ForChItem::Carry(_)));
+}
+
+/// Binds the index of every loop of the chain, numbered from `next`.
+fn number_loops(stmts: &mut [ForChItem], next: &mut usize) {
+    for item in stmts {
+        match item {
+            ForChItem::ForIn(for_in) => {
+                for_in.index = Some(synthetic_ident(&format!("__index{}", next)));
+                *next += 1;
+            }
+            ForChItem::Attributed(attributed) => {
+                number_loops(std::slice::from_mut(&mut *attributed.clause), next)
+            }
+            _ => {}
+        }
+    }
+}
+
+impl Parse for ForStream {
+    fn parse(input: ParseStream) -> syn::Result<Self> {
+        if input.peek(Token![move]) && input.peek2(syn::token::Brace) {
+            let move_tok = input.parse()?;
+            let content;
+            syn::braced!(content in input);
+            return Ok(Self {
+                move_tok,
+                stmts: content.parse::<ForCh>()?.stmts,
+            });
+        }
+
+        Ok(Self {
+            move_tok: None,
+            stmts: input.parse::<ForCh>()?.stmts,
+        })
+    }
+}
+
+impl Parse for ForRetain {
+    fn parse(input: ParseStream) -> syn::Result<Self> {
+        let target = input.parse()?;
+        let _comma_tok = input.parse()?;
+        let _or1_tok = input.parse()?;
+        let pat = input.parse()?;
+        let _or2_tok = input.parse()?;
+        let content;
+        syn::braced!(content in input);
+        Ok(Self {
+            target,
+            _comma_tok,
+            _or1_tok,
+            pat,
+            _or2_tok,
+            stmts: content.parse::<ForCh>()?.stmts,
+        })
+    }
+}
+
+impl Parse for ForSample {
+    fn parse(input: ParseStream) -> syn::Result<Self> {
+        let k = input.parse()?;
+        input.parse::<Token![,]>()?;
+        let rng = if input.peek(syn::token::Brace) {
+            None
+        } else {
+            let rng = input.parse()?;
+            input.parse::<Token![,]>()?;
+            Some(rng)
+        };
+        let content;
+        syn::braced!(content in input);
+        Ok(Self {
+            k,
+            rng,
+            stmts: content.parse::<ForCh>()?.stmts,
+        })
+    }
+}
+
+impl Parse for ForWindows {
+    fn parse(input: ParseStream) -> syn::Result<Self> {
+        let size = input.parse()?;
+        let _comma_tok = input.parse()?;
+        let content;
+        syn::braced!(content in input);
+        Ok(Self {
+            size,
+            _comma_tok,
+            stmts: content.parse::<ForCh>()?.stmts,
+        })
+    }
+}
+
+impl Parse for ForVecTake {
+    fn parse(input: ParseStream) -> syn::Result<Self> {
+        let limit = input.parse()?;
+        let _comma_tok = input.parse()?;
+        let content;
+        syn::braced!(content in input);
+        Ok(Self {
+            limit,
+            _comma_tok,
+            stmts: content.parse::<ForCh>()?.stmts,
+        })
+    }
+}
+
+impl Parse for ForCapacity {
+    fn parse(input: ParseStream) -> syn::Result<Self> {
+        // `cap = n;` on its own is an assignment in the chain
+        let fork = input.fork();
+        if let Ok(for_capacity) = fork.call(Self::parse_hinted) {
+            if fork.is_empty() {
+                input.advance_to(&fork);
+                return Ok(for_capacity);
+            }
+        }
+
+        Ok(Self {
+            cap: None,
+            stmts: input.parse::<ForCh>()?.stmts,
+        })
     }
 }
 
-fn for_in_zippings<'a>(
-    mut items: impl Iterator<Item = &'a ForInItem>,
-) -> (proc_macro2::TokenStream, proc_macro2::TokenStream) {
-    let (fst_pat, fst_iter) = if let Some(fst) = items.next() {
-        (&fst.pat, &fst.iter)
-    } else {
-        return Default::default();
-    };
-
-    let (snd_pat, snd_iter) = for_in_zippings(items);
-    if snd_pat.is_empty() || snd_iter.is_empty() {
-        return (quote! { #fst_pat }, quote! { #fst_iter });
+impl ForCapacity {
+    fn parse_hinted(input: ParseStream) -> syn::Result<Self> {
+        input.parse::<kw::cap>()?;
+        input.parse::<Token![=]>()?;
+        let cap = input.parse()?;
+        input.parse::<Token![,]>()?;
+        let content;
+        syn::braced!(content in input);
+        Ok(Self {
+            cap: Some(cap),
+            stmts: content.parse::<ForCh>()?.stmts,
+        })
     }
 
-    (
-        quote! { (#fst_pat, #snd_pat) },
-        quote! { (#fst_iter).into_iter().zip(#snd_iter) },
-    )
+    /// An empty `collection`, with the capacity if one is given.
+    fn init(&self, collection: proc_macro2::TokenStream) -> proc_macro2::TokenStream {
+        match &self.cap {
+            Some(cap) => quote! { #collection::with_capacity(#cap) },
+            None => quote! { #collection::new() },
+        }
+    }
 }
 
-impl Parse for IfLet {
+impl Parse for ForPushInto {
     fn parse(input: ParseStream) -> syn::Result<Self> {
+        let sink = input.parse()?;
+        let _comma_tok = input.parse()?;
+        let content;
+        syn::braced!(content in input);
         Ok(Self {
-            _if_tok: input.parse()?,
-            _let_tok: input.parse()?,
-            pat: input.parse()?,
-            _eq_tok: input.parse()?,
-            expr: input.parse()?,
-            _semi_tok: input.parse()?,
+            sink,
+            _comma_tok,
+            stmts: content.parse::<ForCh>()?.stmts,
         })
     }
 }
 
-impl Parse for IfGuard {
+impl Parse for ForCollectWith {
     fn parse(input: ParseStream) -> syn::Result<Self> {
+        let target = input.parse()?;
+        let _comma_tok = input.parse()?;
+        let content;
+        syn::braced!(content in input);
         Ok(Self {
-            _if_tok: input.parse()?,
-            expr: input.parse()?,
-            _semi_tok: input.parse()?,
+            target,
+            _comma_tok,
+            stmts: content.parse::<ForCh>()?.stmts,
         })
     }
 }
 
-impl Parse for IfLetElse {
+impl Parse for ForTryFold {
     fn parse(input: ParseStream) -> syn::Result<Self> {
+        let init = input.parse()?;
+        let _comma_tok = input.parse()?;
+        let acc = input.parse()?;
+        let _acc_comma_tok = input.parse()?;
+        let content;
+        syn::braced!(content in input);
         Ok(Self {
-            _if_tok: input.parse()?,
-            _let_tok: input.parse()?,
-            pat: input.parse()?,
-            _eq_tok: input.parse()?,
-            expr: input.parse()?,
-            _else_tok: input.parse()?,
-            block: input.parse()?,
-            _semi_tok: input.parse()?,
+            init,
+            _comma_tok,
+            acc,
+            _acc_comma_tok,
+            stmts: content.parse::<ForCh>()?.stmts,
         })
     }
 }
 
-impl Parse for IfElse {
+impl Parse for ForChLowered {
     fn parse(input: ParseStream) -> syn::Result<Self> {
+        let mut lowering = None;
+        for attr in input.call(syn::Attribute::parse_inner)? {
+            if lowering.is_some() {
+                return Err(syn::Error::new_spanned(
+                    attr,
+                    "the lowering is already chosen",
+                ));
+            }
+            lowering = Some(
+                if attr.path.is_ident("for_each") && attr.tokens.is_empty() {
+                    Lowering::ForEach(attr)
+                } else if attr.path.is_ident("for_loop") && attr.tokens.is_empty() {
+                    Lowering::ForLoop
+                } else {
+                    return Err(syn::Error::new_spanned(
+                        attr,
+                        "expected `#![for_each]` or `#![for_loop]`",
+                    ));
+                },
+            );
+        }
+
         Ok(Self {
-            _if_tok: input.parse()?,
-            expr: input.parse()?,
-            _else_tok: input.parse()?,
-            block: input.parse()?,
-            _semi_tok: input.parse()?,
+            lowering,
+            chain: input.parse()?,
         })
     }
 }
 
-impl Parse for ForCh {
+impl Parse for ForFormat {
     fn parse(input: ParseStream) -> syn::Result<Self> {
+        let (tokens, indexed) = expand_indices(input.parse()?);
+        let mut for_format: Self = syn::parse::Parser::parse2(Self::parse_expanded, tokens)?;
+        if indexed {
+            number_loops(&mut for_format.stmts, &mut 0);
+        }
+        hoist_carries(&mut for_format.stmts);
+
+        Ok(for_format)
+    }
+}
+
+impl ForFormat {
+    /// The format string is the first string literal that is followed by
+    /// `,` or by the end of input.
+    fn parse_expanded(input: ParseStream) -> syn::Result<Self> {
         let mut stmts = vec![];
-        while !input.is_empty() {
-            let fork = input.fork();
-            if let Ok(if_guard) = fork.parse::<IfGuard>() {
-                input.advance_to(&fork);
-                stmts.push(ForChItem::IfGuard(if_guard));
-                continue;
+        loop {
+            if input.is_empty() {
+                return Err(input.error("expected a format string"));
             }
 
-            let fork = input.fork();
-            if let Ok(if_let) = fork.parse::<IfLet>() {
-                input.advance_to(&fork);
-                stmts.push(ForChItem::IfLet(if_let));
-                continue;
+            if input.peek(syn::LitStr) {
+                let fork = input.fork();
+                fork.parse::<syn::LitStr>()?;
+                if fork.is_empty() || fork.peek(Token![,]) {
+                    break;
+                }
             }
 
-            let fork = input.fork();
-            if let Ok(if_else) = fork.parse::<IfElse>() {
-                input.advance_to(&fork);
-                stmts.push(ForChItem::IfElse(if_else));
-            }
+            stmts.push(input.parse()?);
+        }
 
-            let fork = input.fork();
-            if let Ok(if_let_else) = fork.parse::<IfLetElse>() {
-                input.advance_to(&fork);
-                stmts.push(ForChItem::IfLetElse(if_let_else));
-            }
+        Ok(Self {
+            stmts,
+            fmt: input.parse()?,
+        })
+    }
+}
 
-            let fork = input.fork();
-            if let Ok(for_in) = fork.parse::<ForIn>() {
-                input.advance_to(&fork);
-                stmts.push(ForChItem::ForIn(for_in));
-                continue;
+fn for_body(stmts: &[ForChItem]) -> proc_macro2::TokenStream {
+    // consecutive guards become one `if a && b && ..`
+    let guards = stmts.iter().map_while(plain_guard).count();
+    if guards > 1 && cfg!(not(feature = "trace")) {
+        let cond = stmts[..guards]
+            .iter()
+            .filter_map(plain_guard)
+            .map(|expr| quote! { (#expr) })
+            .reduce(|lhs, rhs| quote! { #lhs && #rhs });
+        let rest = for_body(&stmts[guards..]);
+        return quote! {
+            if #cond {
+                #rest
             }
+        };
+    }
+
+    match stmts {
+        [item, rest @ ..] => for_item(item, for_body(rest)),
+        [] => proc_macro2::TokenStream::new(),
+    }
+}
+
+/// Lowers a chain of one `for` followed by clauses without loops or jumps
+/// to `Iterator::for_each`.
+fn for_each_body(stmts: &[ForChItem]) -> Option<proc_macro2::TokenStream> {
+    let (for_in, rest) = closure_chain(stmts)?;
+    if rest.iter().any(is_for_in) {
+        return None;
+    }
+
+    let (pat, iter) = for_in.header();
+    let body = for_body(rest);
+    Some(quote! {
+        ::core::iter::Iterator::for_each(
+            ::core::iter::IntoIterator::into_iter(#iter),
+            |#pat| {
+                #body
+            },
+        )
+    })
+}
+
+/// Splits a chain whose clauses after the first `for` may run in a closure
+/// taking its elements.
+fn closure_chain(stmts: &[ForChItem]) -> Option<(&ForIn, &[ForChItem])> {
+    let (for_in, rest) = match stmts {
+        [ForChItem::ForIn(for_in), rest @ ..] => (for_in, rest),
+        _ => return None,
+    };
+    let plain = for_in.label.is_none()
+        && for_in.index.is_none()
+        && !for_in.is_await()
+        && for_in.placeholders().next().is_none();
+    if !plain || jumps(rest) {
+        return None;
+    }
+    Some((for_in, rest))
+}
 
-            stmts.push(ForChItem::Stmt(input.parse()?));
+/// Whether a clause may leave the current iteration other than by finishing
+/// it: `break`, `continue`, `return`, `?`, `.await` or `yield`, including
+/// the `continue` of an `else continue` and the `return` of an `else err`.
+fn jumps(stmts: &[ForChItem]) -> bool {
+    let mut jumps = Jumps::default();
+    for item in stmts {
+        if is_else_continue(item) || is_else_err(item) || is_cf(item) {
+            return true;
         }
+        visit_clause(&mut jumps, item);
+    }
+    jumps.found
+}
 
-        Ok(Self { stmts })
+#[derive(Default)]
+struct Jumps {
+    found: bool,
+    /// the caller's own loops around the current expression
+    loops: usize,
+}
+
+impl Jumps {
+    fn in_loop(&mut self, visit: impl FnOnce(&mut Self)) {
+        self.loops += 1;
+        visit(self);
+        self.loops -= 1;
     }
 }
 
-fn for_body(stmts: &[ForChItem]) -> proc_macro2::TokenStream {
+impl<'ast> Visit<'ast> for Jumps {
+    fn visit_expr_break(&mut self, expr: &'ast syn::ExprBreak) {
+        self.found |= expr.label.is_some() || self.loops == 0;
+        syn::visit::visit_expr_break(self, expr);
+    }
+
+    fn visit_expr_continue(&mut self, expr: &'ast syn::ExprContinue) {
+        self.found |= expr.label.is_some() || self.loops == 0;
+    }
+
+    fn visit_expr_return(&mut self, _: &'ast syn::ExprReturn) {
+        self.found = true;
+    }
+
+    fn visit_expr_try(&mut self, _: &'ast syn::ExprTry) {
+        self.found = true;
+    }
+
+    fn visit_expr_await(&mut self, _: &'ast syn::ExprAwait) {
+        self.found = true;
+    }
+
+    fn visit_expr_yield(&mut self, _: &'ast syn::ExprYield) {
+        self.found = true;
+    }
+
+    fn visit_expr_loop(&mut self, expr: &'ast syn::ExprLoop) {
+        self.in_loop(|jumps| syn::visit::visit_expr_loop(jumps, expr));
+    }
+
+    fn visit_expr_while(&mut self, expr: &'ast syn::ExprWhile) {
+        self.in_loop(|jumps| syn::visit::visit_expr_while(jumps, expr));
+    }
+
+    fn visit_expr_for_loop(&mut self, expr: &'ast syn::ExprForLoop) {
+        self.in_loop(|jumps| syn::visit::visit_expr_for_loop(jumps, expr));
+    }
+
+    // closures and items have their own control flow
+    fn visit_expr_closure(&mut self, _: &'ast syn::ExprClosure) {}
+    fn visit_expr_async(&mut self, _: &'ast syn::ExprAsync) {}
+    fn visit_item(&mut self, _: &'ast syn::Item) {}
+    // what a macro expands to is unknown, so look for jumps in its tokens
+    fn visit_macro(&mut self, mac: &'ast syn::Macro) {
+        self.found |= tokens_jump(mac.tokens.clone());
+    }
+}
+
+/// Whether the source of the first `for` borrows or moves a field. Before
+/// edition 2021 a closure captures the whole variable, which would conflict
+/// with the clauses using its other fields, so such chains keep the loop.
+fn projects_fields(stmts: &[ForChItem]) -> bool {
+    struct Fields(bool);
+
+    impl<'ast> Visit<'ast> for Fields {
+        fn visit_expr_field(&mut self, _: &'ast syn::ExprField) {
+            self.0 = true;
+        }
+    }
+
+    let iter = match stmts {
+        [ForChItem::ForIn(for_in), ..] => for_in.header().1,
+        _ => return false,
+    };
+    let mut fields = Fields(false);
+    match syn::parse2::<syn::Expr>(iter) {
+        Ok(iter) => fields.visit_expr(&iter),
+        Err(_) => return true,
+    }
+    fields.0
+}
+
+fn tokens_jump(tokens: proc_macro2::TokenStream) -> bool {
+    tokens.into_iter().any(|token| match token {
+        proc_macro2::TokenTree::Group(group) => tokens_jump(group.stream()),
+        proc_macro2::TokenTree::Ident(ident) => ["break", "continue", "return", "await", "yield"]
+            .iter()
+            .any(|kw| ident == kw),
+        proc_macro2::TokenTree::Punct(punct) => punct.as_char() == '?',
+        proc_macro2::TokenTree::Literal(_) => false,
+    })
+}
+
+/// Lowers the chain with the rest of its first `for` spawned into `scope`.
+fn scope_body(stmts: &[ForChItem], scope: &proc_macro2::Ident) -> proc_macro2::TokenStream {
     match stmts {
-        [item, rest @ ..] => {
+        [item, rest @ ..] if is_for_in(item) => {
             let rest = for_body(rest);
-            match item {
-                ForChItem::Stmt(s) => quote! { #s #rest },
-                ForChItem::IfLet(if_let) => {
-                    let pat = &if_let.pat;
-                    let expr = &if_let.expr;
-                    quote! {
-                        if let #pat = #expr {
-                            #rest
-                        }
+            for_item(item, quote! { #scope.spawn(move |_| { #rest }); })
+        }
+        [item, rest @ ..] => for_item(item, scope_body(rest, scope)),
+        [] => quote! {},
+    }
+}
+
+/// The condition of an `if expr;` guard, unless it is an `if let` or a
+/// let-chain.
+fn plain_guard(item: &ForChItem) -> Option<&syn::Expr> {
+    match item {
+        ForChItem::IfGuard(IfGuard { expr, .. }) if !is_let_chain(expr) => Some(expr),
+        _ => None,
+    }
+}
+
+/// The `&&`-joined conditions of `expr`.
+fn conjuncts(expr: &syn::Expr) -> Vec<&syn::Expr> {
+    match expr {
+        syn::Expr::Binary(syn::ExprBinary {
+            attrs,
+            left,
+            op: syn::BinOp::And(_),
+            right,
+        }) if attrs.is_empty() => {
+            let mut conds = conjuncts(left);
+            conds.extend(conjuncts(right));
+            conds
+        }
+        expr => vec![expr],
+    }
+}
+
+fn is_let_chain(expr: &syn::Expr) -> bool {
+    conjuncts(expr)
+        .iter()
+        .any(|cond| matches!(cond, syn::Expr::Let(_)))
+}
+
+/// Nests `then` in one `if` per condition of a let-chain, so that its `let`s
+/// bind on every edition, unless the `let_chains` feature asks for native
+/// let-chains. Other guards stay a single `if`.
+fn nest_guard(
+    expr: &syn::Expr,
+    then: proc_macro2::TokenStream,
+    otherwise: Option<proc_macro2::TokenStream>,
+) -> proc_macro2::TokenStream {
+    let conds = if is_let_chain(expr) && cfg!(not(feature = "let_chains")) {
+        conjuncts(expr)
+    } else {
+        vec![expr]
+    };
+    conds
+        .into_iter()
+        .rev()
+        .fold(then, |then, cond| match &otherwise {
+            Some(otherwise) => quote! {
+                if #cond {
+                    #then
+                } else {
+                    #otherwise
+                }
+            },
+            None => quote! {
+                if #cond {
+                    #then
+                }
+            },
+        })
+}
+
+/// With the `trace` feature, logs that the guard `clause` skipped the rest.
+fn trace_skip(clause: proc_macro2::TokenStream) -> Option<proc_macro2::TokenStream> {
+    if cfg!(not(feature = "trace")) {
+        return None;
+    }
+
+    let source = clause.to_string();
+    Some(quote! {
+        ::log::trace!("for_ch: skipped by `{}`", #source);
+    })
+}
+
+fn for_item(item: &ForChItem, rest: proc_macro2::TokenStream) -> proc_macro2::TokenStream {
+    match item {
+        ForChItem::Stmt(s) => quote! { #s #rest },
+        ForChItem::Do(do_block) => {
+            let block = &do_block.block;
+            quote! { #block #rest }
+        }
+        ForChItem::Carry(carry) => quote! { #carry #rest },
+        ForChItem::Cf(cf) => {
+            let expr = &cf.expr;
+            let value = synthetic_ident("__value");
+            quote! {
+                match #expr {
+                    ::core::ops::ControlFlow::Continue(()) => {
+                        #rest
                     }
+                    ::core::ops::ControlFlow::Break(#value) => break 'for_ch #value,
                 }
-                ForChItem::ForIn(for_in) => {
-                    quote! {
-                        #for_in {
+            }
+        }
+        ForChItem::IfLet(if_let) => {
+            let pat = &if_let.pat;
+            let expr = &if_let.expr;
+            match trace_skip(quote! { if let #pat = #expr }) {
+                Some(trace) => quote! {
+                    if let #pat = #expr {
+                        #rest
+                    } else {
+                        #trace
+                    }
+                },
+                None => quote! {
+                    if let #pat = #expr {
+                        #rest
+                    }
+                },
+            }
+        }
+        ForChItem::ForIn(for_in) => {
+            let rest = for_in.placeholders().fold(rest, |rest, (binding, item)| {
+                let pat = &item.pat;
+                let value = match &item.try_tok {
+                    Some(try_tok) => quote! { #binding #try_tok },
+                    None => binding,
+                };
+                match item.refutable {
+                    Some(_) => quote! {
+                        if let #pat = #value {
                             #rest
                         }
+                    },
+                    None => quote! {
+                        let #pat = #value;
+                        #rest
+                    },
+                }
+            });
+            let rest = for_in.with_index(rest);
+            if for_in.is_await() {
+                return for_await(for_in, rest);
+            }
+
+            quote! {
+                #for_in {
+                    #rest
+                }
+            }
+        }
+        ForChItem::IfGuard(if_guard) => {
+            let expr = &if_guard.expr;
+            nest_guard(expr, rest, trace_skip(quote! { if #expr }))
+        }
+        ForChItem::IfLetElse(if_let_else) => {
+            let pat = &if_let_else.pat;
+            let expr = &if_let_else.expr;
+            let block = &if_let_else.block;
+            quote! {
+                if let #pat = #expr {
+                    #rest
+                } else #block
+            }
+        }
+        ForChItem::IfLetElseErr(if_let_else_err) => {
+            let pat = &if_let_else_err.pat;
+            let expr = &if_let_else_err.expr;
+            let err = synthetic_ident("__err");
+            quote! {
+                match #expr {
+                    #pat => {
+                        #rest
+                    }
+                    ::core::result::Result::Err(#err) => {
+                        return ::core::result::Result::Err(::core::convert::From::from(#err));
                     }
+                    #[allow(unreachable_patterns)]
+                    ::core::result::Result::Ok(_) => {}
                 }
-                ForChItem::IfGuard(if_guard) => {
-                    let expr = &if_guard.expr;
-                    quote! {
-                        if #expr {
-                            #rest
-                        }
+            }
+        }
+        ForChItem::GuardLet(guard_let) => {
+            let pat = &guard_let.pat;
+            // a `let` needs its or-patterns in parentheses
+            let pat = match pat {
+                syn::Pat::Or(_) => quote! { (#pat) },
+                pat => quote! { #pat },
+            };
+            let expr = &guard_let.expr;
+            let block = &guard_let.block;
+            quote! {
+                let #pat = #expr else #block;
+                #rest
+            }
+        }
+        ForChItem::IfElse(if_else) => {
+            let expr = &if_else.expr;
+            let else_branch = &if_else.else_branch;
+            match &if_else.continue_tok {
+                Some(continue_tok) => quote! {
+                    if #expr {
+                        #rest
+                    } else {
+                        #else_branch;
+                        #continue_tok;
+                    }
+                },
+                None => quote! {
+                    if #expr {
+                        #rest
+                    } else #else_branch
+                },
+            }
+        }
+        ForChItem::Attributed(attributed) => {
+            let attrs = &attributed.attrs;
+            let cfgs = &attributed.cfgs;
+            if cfgs.is_empty() {
+                let clause = for_item(&attributed.clause, rest);
+                return quote! {
+                    #(#attrs)* {
+                        #clause
                     }
+                };
+            }
+
+            // a disabled clause is transparent: the continuation runs once
+            let clause = for_item(&attributed.clause, rest.clone());
+            quote! {
+                #[cfg(all(#(#cfgs),*))]
+                #(#attrs)* {
+                    #clause
                 }
-                ForChItem::IfLetElse(if_let_else) => {
-                    let pat = &if_let_else.pat;
-                    let expr = &if_let_else.expr;
-                    let block = &if_let_else.block;
-                    quote! {
-                        if let #pat = #expr {
-                            #rest
-                        } else #block
+                #[cfg(not(all(#(#cfgs),*)))]
+                {
+                    #rest
+                }
+            }
+        }
+    }
+}
+
+/// Lowers `for await`, which is never zipped, to a `while let` polling the
+/// stream.
+fn for_await(for_in: &ForIn, rest: proc_macro2::TokenStream) -> proc_macro2::TokenStream {
+    let label = &for_in.label;
+    let item = &for_in.items[0];
+    let (binding, source) = match &for_in.index {
+        Some(index) => {
+            let binding = item.binding(0);
+            let source = &item.iter;
+            (
+                quote! { (#index, #binding) },
+                quote! { ::futures::stream::StreamExt::enumerate(#source) },
+            )
+        }
+        None => (item.binding(0), item.iter.to_token_stream()),
+    };
+
+    let stream = synthetic_ident("__stream");
+    let next = quote! { ::futures::stream::StreamExt::next(&mut #stream) };
+    match &item.within {
+        None => quote! {
+            {
+                let mut #stream = ::core::pin::pin!(#source);
+                #label while let ::core::option::Option::Some(#binding) = #next.await {
+                    #rest
+                }
+            }
+        },
+        Some(duration) => {
+            let deadline = synthetic_ident("__deadline");
+            quote! {
+                {
+                    let #deadline = ::tokio::time::Instant::now() + (#duration);
+                    let mut #stream = ::core::pin::pin!(#source);
+                    #label while let ::core::result::Result::Ok(::core::option::Option::Some(#binding)) =
+                        ::tokio::time::timeout_at(#deadline, #next).await
+                    {
+                        #rest
                     }
                 }
-                ForChItem::IfElse(if_else) => {
-                    let expr = &if_else.expr;
-                    let block = &if_else.block;
-                    quote! {
-                        if #expr {
-                            #rest
-                        } else #block
+            }
+        }
+    }
+}
+
+/// what a lazy chain is built from
+#[derive(Clone, Copy)]
+enum Lazy {
+    /// for_iter!
+    Iter,
+    /// for_stream!, where `move_env` makes the outermost closure capture the
+    /// enclosing scope by value
+    Stream { move_env: bool },
+}
+
+impl Lazy {
+    /// The kind for the clauses nested in the closure of a `for`, which always
+    /// capture the bindings of the outer clauses by value.
+    fn nested(self) -> Self {
+        match self {
+            Lazy::Iter => Lazy::Iter,
+            Lazy::Stream { .. } => Lazy::Stream { move_env: true },
+        }
+    }
+
+    fn move_tok(self) -> Option<Token![move]> {
+        match self {
+            Lazy::Iter | Lazy::Stream { move_env: true } => Some(Default::default()),
+            Lazy::Stream { move_env: false } => None,
+        }
+    }
+
+    fn once(self, terminal: &syn::Expr) -> proc_macro2::TokenStream {
+        match self {
+            Lazy::Iter => quote! { ::core::iter::once(#terminal) },
+            Lazy::Stream { .. } => quote! {
+                ::futures::stream::iter(::core::iter::once(#terminal))
+            },
+        }
+    }
+
+    /// Flattens an `Option` of an iterator (or stream) into an iterator (or
+    /// stream).
+    fn flatten_option(self, option: proc_macro2::TokenStream) -> proc_macro2::TokenStream {
+        match self {
+            Lazy::Iter => quote! {
+                ::core::iter::Iterator::flatten(::core::option::Option::into_iter(#option))
+            },
+            Lazy::Stream { .. } => quote! {
+                ::futures::stream::StreamExt::flatten(::futures::stream::iter(#option))
+            },
+        }
+    }
+}
+
+/// Lowers the chain to an iterator (or stream) over its trailing expression.
+fn iter_body(stmts: &[ForChItem], terminal: &syn::Expr, lazy: Lazy) -> proc_macro2::TokenStream {
+    match stmts {
+        [item, rest @ ..] => {
+            let nested = if is_for_in(item) { lazy.nested() } else { lazy };
+            iter_item(item, iter_body(rest, terminal, nested), lazy)
+        }
+        [] => lazy.once(terminal),
+    }
+}
+
+fn is_else_continue(item: &ForChItem) -> bool {
+    match item {
+        ForChItem::IfElse(IfElse {
+            continue_tok: Some(_),
+            ..
+        }) => true,
+        ForChItem::Attributed(attributed) => is_else_continue(&attributed.clause),
+        _ => false,
+    }
+}
+
+fn is_else_err(item: &ForChItem) -> bool {
+    match item {
+        ForChItem::IfLetElseErr(_) => true,
+        ForChItem::Attributed(attributed) => is_else_err(&attributed.clause),
+        _ => false,
+    }
+}
+
+fn is_cf(item: &ForChItem) -> bool {
+    match item {
+        ForChItem::Cf(_) => true,
+        ForChItem::Attributed(attributed) => is_cf(&attributed.clause),
+        _ => false,
+    }
+}
+
+fn is_for_in(item: &ForChItem) -> bool {
+    match item {
+        ForChItem::ForIn(_) => true,
+        ForChItem::Attributed(attributed) => is_for_in(&attributed.clause),
+        _ => false,
+    }
+}
+
+fn iter_item(
+    item: &ForChItem,
+    rest: proc_macro2::TokenStream,
+    lazy: Lazy,
+) -> proc_macro2::TokenStream {
+    match item {
+        ForChItem::Stmt(s) => quote! {
+            {
+                #s
+                #rest
+            }
+        },
+        ForChItem::Do(do_block) => {
+            let block = &do_block.block;
+            quote! {
+                {
+                    #block
+                    #rest
+                }
+            }
+        }
+        ForChItem::Carry(carry) => {
+            syn::Error::new_spanned(&carry.name, "`carry` isn't lazy").to_compile_error()
+        }
+        ForChItem::Cf(cf) => {
+            syn::Error::new_spanned(&cf.expr, "`cf` isn't lazy").to_compile_error()
+        }
+        ForChItem::IfLet(if_let) => {
+            let pat = &if_let.pat;
+            let expr = &if_let.expr;
+            lazy.flatten_option(quote! {
+                if let #pat = #expr {
+                    ::core::option::Option::Some(#rest)
+                } else {
+                    ::core::option::Option::None
+                }
+            })
+        }
+        ForChItem::ForIn(for_in) if for_in.is_await() && matches!(lazy, Lazy::Iter) => {
+            syn::Error::new_spanned(for_in.items[0].await_tok, "`for await` isn't lazy")
+                .to_compile_error()
+        }
+        ForChItem::ForIn(ForIn { items, .. })
+            if items.iter().any(|item| item.try_tok.is_some()) =>
+        {
+            let try_tok = items.iter().find_map(|item| item.try_tok);
+            syn::Error::new_spanned(try_tok, "`?` on the elements of a `for` isn't lazy")
+                .to_compile_error()
+        }
+        ForChItem::ForIn(ForIn { items, .. }) if items[0].within.is_some() => {
+            syn::Error::new_spanned(
+                &items[0].within,
+                "`within` isn't supported by `for_stream!`",
+            )
+            .to_compile_error()
+        }
+        ForChItem::ForIn(for_in) => {
+            let (pat, iter) = match lazy {
+                Lazy::Stream { .. } if for_in.is_await() => {
+                    let (pat, iter) = for_in.header();
+                    match &for_in.index {
+                        Some(index) => (
+                            quote! { (#index, #pat) },
+                            quote! { ::futures::stream::StreamExt::enumerate(#iter) },
+                        ),
+                        None => (pat, iter),
+                    }
+                }
+                _ => for_in.indexed_header(),
+            };
+            let rest = for_in.placeholders().fold(rest, |rest, (binding, item)| {
+                let pat = &item.pat;
+                lazy.flatten_option(quote! {
+                    if let #pat = #binding {
+                        ::core::option::Option::Some(#rest)
+                    } else {
+                        ::core::option::Option::None
                     }
+                })
+            });
+            let rest = match &for_in.index {
+                Some(_) => {
+                    let rest = for_in.with_index(rest);
+                    quote! { { #rest } }
+                }
+                None => rest,
+            };
+            let move_tok = lazy.move_tok();
+            match lazy {
+                Lazy::Iter => quote! {
+                    ::core::iter::Iterator::flat_map(
+                        ::core::iter::IntoIterator::into_iter(#iter),
+                        #move_tok |#pat| #rest,
+                    )
+                },
+                Lazy::Stream { .. } if for_in.is_await() => quote! {
+                    ::futures::stream::StreamExt::flat_map(#iter, #move_tok |#pat| #rest)
+                },
+                Lazy::Stream { .. } => quote! {
+                    ::futures::stream::StreamExt::flat_map(
+                        ::futures::stream::iter(#iter),
+                        #move_tok |#pat| #rest,
+                    )
+                },
+            }
+        }
+        ForChItem::IfGuard(if_guard) => lazy.flatten_option(nest_guard(
+            &if_guard.expr,
+            quote! { ::core::option::Option::Some(#rest) },
+            Some(quote! { ::core::option::Option::None }),
+        )),
+        ForChItem::IfLetElseErr(if_let_else_err) => {
+            syn::Error::new_spanned(if_let_else_err.err_tok, "`else err` isn't lazy")
+                .to_compile_error()
+        }
+        ForChItem::IfLetElse(IfLetElse {
+            pat, expr, block, ..
+        })
+        | ForChItem::GuardLet(GuardLet {
+            pat, expr, block, ..
+        }) => lazy.flatten_option(quote! {
+            if let #pat = #expr {
+                ::core::option::Option::Some(#rest)
+            } else {
+                #block;
+                ::core::option::Option::None
+            }
+        }),
+        ForChItem::IfElse(if_else) => {
+            let expr = &if_else.expr;
+            let else_branch = &if_else.else_branch;
+            lazy.flatten_option(quote! {
+                if #expr {
+                    ::core::option::Option::Some(#rest)
+                } else {
+                    #else_branch;
+                    ::core::option::Option::None
+                }
+            })
+        }
+        ForChItem::Attributed(attributed) => {
+            let attrs = &attributed.attrs;
+            let cfgs = &attributed.cfgs;
+            let iter = synthetic_ident("__iter");
+            if cfgs.is_empty() {
+                let clause = iter_item(&attributed.clause, rest, lazy);
+                return quote! {
+                    {
+                        #(#attrs)*
+                        let #iter = #clause;
+                        #iter
+                    }
+                };
+            }
+
+            let clause = iter_item(&attributed.clause, rest.clone(), lazy);
+            quote! {
+                {
+                    #[cfg(all(#(#cfgs),*))]
+                    #(#attrs)*
+                    let #iter = #clause;
+                    #[cfg(not(all(#(#cfgs),*)))]
+                    let #iter = #rest;
+                    #iter
                 }
             }
         }
-        [] => proc_macro2::TokenStream::new(),
     }
 }
@@ -0,0 +1,51 @@
+use for_ch::{for_ch, for_vec};
+
+#[test]
+fn cfg_enabled_clause() {
+    let v = for_vec! {
+        for x in 0..2;
+        #[cfg(all())]
+        for y in 0..2;
+        (x, y)
+    };
+    assert_eq!(v, [(0, 0), (0, 1), (1, 0), (1, 1)]);
+}
+
+#[test]
+fn cfg_disabled_clause_is_transparent() {
+    let mut n = 0;
+    for_ch! {
+        #[cfg(any())]
+        for _ in 0..10;
+        #[cfg(any())]
+        if false;
+        n += 1;
+    }
+    assert_eq!(n, 1);
+}
+
+#[test]
+fn cfg_feature_clause() {
+    let v = for_vec! {
+        for x in 0..3;
+        #[cfg(feature = "extra")]
+        if x != 1;
+        x
+    };
+    if cfg!(feature = "extra") {
+        assert_eq!(v, [0, 2]);
+    } else {
+        assert_eq!(v, [0, 1, 2]);
+    }
+}
+
+#[test]
+fn forwarded_attribute() {
+    let mut n = 0;
+    for_ch! {
+        #[allow(unused_variables)]
+        for x in 0..3;
+        n += 1;
+    }
+    assert_eq!(n, 3);
+}
@@ -0,0 +1,350 @@
+use for_ch::{
+    for_ch, for_collect_with, for_flat_vec, for_format, for_minmax, for_push_into, for_retain,
+    for_string, for_unzip, for_vec, for_vec_take, for_windows_vec,
+};
+
+#[test]
+fn string_from_chars() {
+    let s = for_string! {
+        for c in "hello world".chars();
+        if c != 'o';
+        c
+    };
+    assert_eq!(s, "hell wrld");
+}
+
+#[test]
+fn string_from_strs() {
+    let words = ["foo", "bar", "baz"];
+    let s = for_string! {
+        for w in words.iter();
+        if w.starts_with('b');
+        *w
+    };
+    assert_eq!(s, "barbaz");
+}
+
+#[test]
+fn string_from_format() {
+    let s = for_format! {
+        for x in 0..3;
+        for y in 0..x;
+        "({}, {})", x, y
+    };
+    assert_eq!(s, "(1, 0)(2, 0)(2, 1)");
+}
+
+#[test]
+fn vec_nested() {
+    let v = for_vec! {
+        for x in 0..3;
+        for y in x..3;
+        (x, y)
+    };
+    assert_eq!(v, [(0, 0), (0, 1), (0, 2), (1, 1), (1, 2), (2, 2)]);
+}
+
+#[test]
+fn vec_of_vecs() {
+    let grid = vec![vec![1, 2, 3], vec![], vec![4, 5]];
+    let doubled = for_vec! {
+        for row in &grid;
+        for_vec! {
+            for cell in row;
+            cell * 2
+        }
+    };
+    assert_eq!(doubled, vec![vec![2, 4, 6], vec![], vec![8, 10]]);
+
+    let triangle: Vec<Vec<usize>> = for_vec! {
+        for n in 0..4;
+        if n > 0;
+        for_vec! {
+            for k in 0..;
+            if k == n {
+                break 'for_ch;
+            }
+            k
+        }
+    };
+    assert_eq!(triangle, vec![vec![0], vec![0, 1], vec![0, 1, 2]]);
+}
+
+#[test]
+fn flat_vec_concatenates() {
+    let v = for_flat_vec! {
+        for x in 1..4;
+        if x != 2;
+        vec![x; x]
+    };
+    assert_eq!(v, [1, 3, 3, 3]);
+
+    let words = ["ab", "", "cde"];
+    let chars: Vec<char> = for_flat_vec! {
+        for w in words;
+        for upper in [false, true];
+        w.chars().map(move |c| if upper { c.to_ascii_uppercase() } else { c })
+    };
+    assert_eq!(chars, ['a', 'b', 'A', 'B', 'c', 'd', 'e', 'C', 'D', 'E']);
+}
+
+#[test]
+fn capacity_hint() {
+    let n = 100;
+    let v = for_vec!(cap = n, {
+        for x in 0..10;
+        x
+    });
+    assert_eq!(v, (0..10).collect::<Vec<_>>());
+    assert!(v.capacity() >= n);
+
+    let v = for_flat_vec!(cap = 2 * n, {
+        for x in 0..3;
+        [x, x]
+    });
+    assert_eq!(v, [0, 0, 1, 1, 2, 2]);
+    assert!(v.capacity() >= 2 * n);
+
+    let s = for_string!(cap = 64, {
+        for c in "abc".chars();
+        c
+    });
+    assert_eq!(s, "abc");
+    assert!(s.capacity() >= 64);
+
+    // a chain may start by assigning to a `cap` of its own
+    let mut cap = 1;
+    let v = for_vec! {
+        cap = cap.max(2);
+        for x in 0..3;
+        if x < cap;
+        x
+    };
+    assert_eq!(v, [0, 1]);
+    assert_eq!(cap, 2);
+}
+
+#[test]
+fn window_sums() {
+    let xs = [1, 2, 3, 4, 5, 6];
+    let sums = for_windows_vec!(3, {
+        for w in xs;
+        w.iter().sum::<i32>()
+    });
+    assert_eq!(sums, [6, 9, 12, 15]);
+
+    let v: Vec<i32> = xs.to_vec();
+    let rising = for_windows_vec!(2, {
+        for w in &v;
+        if w[0] < w[1];
+        w[1] - w[0]
+    });
+    assert_eq!(rising, [1; 5]);
+
+    let too_wide = for_windows_vec!(7, {
+        for w in xs;
+        w.len()
+    });
+    assert!(too_wide.is_empty());
+
+    let mut means = vec![];
+    for_ch! {
+        for w in xs windows 2 step 2;
+        means.push(f64::from(w[0] + w[1]) / 2.0);
+    }
+    assert_eq!(means, [1.5, 3.5, 5.5]);
+}
+
+#[test]
+fn vec_break_early() {
+    let mut n = 0;
+    let v = for_vec! {
+        for x in 0..10;
+        for y in 0..10;
+        if n < 5 else { break 'for_ch; };
+        n += 1;
+        (x, y)
+    };
+    assert_eq!(v.len(), 5);
+    assert_eq!(v, [(0, 0), (0, 1), (0, 2), (0, 3), (0, 4)]);
+}
+
+#[test]
+fn push_into_existing_vec() {
+    fn fill(out: &mut Vec<(i32, i32)>) {
+        for_push_into!(out, {
+            for x in 1..3;
+            for y in 0..x;
+            (x, y)
+        });
+        for_push_into!(out, {
+            for x in 5..10;
+            if x % 4 == 0;
+            (x, x)
+        });
+    }
+
+    let mut v = vec![(0, 0)];
+    fill(&mut v);
+    assert_eq!(v, [(0, 0), (1, 0), (2, 0), (2, 1), (8, 8)]);
+}
+
+#[test]
+fn minmax_in_one_pass() {
+    let xs = [7, -3, 12, 0, 12, -3, 5];
+    let extremes = for_minmax! {
+        for x in xs;
+        for y in [1, -1];
+        x * y
+    };
+    let products = || xs.iter().flat_map(|x| [*x, -x]);
+    assert_eq!(
+        extremes,
+        Some((products().min().unwrap(), products().max().unwrap()))
+    );
+
+    let words = ["pear", "fig", "apple", "kiwi"];
+    let extremes = for_minmax! {
+        for w in words;
+        if w.len() > 3;
+        w.to_string()
+    };
+    assert_eq!(extremes, Some(("apple".to_string(), "pear".to_string())));
+
+    let extremes: Option<(i32, i32)> = for_minmax! {
+        for x in xs;
+        if x > 100;
+        x
+    };
+    assert_eq!(extremes, None);
+}
+
+#[test]
+fn unzip_pairs() {
+    let (xs, squares) = for_unzip! {
+        for i in 0..5;
+        if i != 2;
+        (i, i * i)
+    };
+    assert_eq!(xs, [0, 1, 3, 4]);
+    assert_eq!(squares, [0, 1, 9, 16]);
+}
+
+#[test]
+fn vec_take_caps_across_loops() {
+    let mut evaluated = 0;
+    let v = for_vec_take!(10, {
+        for x in 0..10;
+        for y in 0..10;
+        evaluated += 1;
+        x * 10 + y
+    });
+    assert_eq!(v, (0..10).collect::<Vec<_>>());
+    assert_eq!(evaluated, 10);
+
+    let v = for_vec_take!(0, {
+        for x in 0..10;
+        x
+    });
+    assert!(v.is_empty());
+}
+
+#[test]
+fn format_panics_at_the_format_string() {
+    use std::sync::{Arc, Mutex};
+
+    struct Failing;
+
+    impl std::fmt::Display for Failing {
+        fn fmt(&self, _: &mut std::fmt::Formatter) -> std::fmt::Result {
+            Err(std::fmt::Error)
+        }
+    }
+
+    let location = Arc::new(Mutex::new(None));
+    let hook = std::panic::take_hook();
+    let recorded = Arc::clone(&location);
+    std::panic::set_hook(Box::new(move |info| {
+        *recorded.lock().unwrap() = info.location().map(|l| (l.file().to_owned(), l.line()));
+    }));
+    let line = line!() + 3;
+    let r = std::panic::catch_unwind(|| {
+        for_format! { for x in [Failing];
+            "{}", x
+        }
+    });
+    std::panic::set_hook(hook);
+
+    assert!(r.is_err());
+    let (file, panic_line) = location.lock().unwrap().clone().unwrap();
+    assert!(file.ends_with("collect.rs"), "{}", file);
+    assert_eq!(panic_line, line);
+}
+
+#[test]
+fn retain_multi_step() {
+    let mut v: Vec<i32> = vec![3, 10, 12, 25, 30];
+    for_retain!(v, |&x| {
+        if x > 5;
+        let half = x / 2;
+        half % 2 == 1
+    });
+    assert_eq!(v, vec![10, 30]);
+
+    let mut words: Vec<&str> = vec!["apple", "kiwi", "fig", "banana"];
+    for_retain!(words, |w| {
+        for c in w.chars();
+        c == 'a'
+    });
+    assert_eq!(words, vec!["apple", "banana"]);
+}
+
+#[test]
+fn retain_through_mut_ref() {
+    fn keep_even(v: &mut Vec<i32>) {
+        for_retain!(v, |&x| { x % 2 == 0 });
+    }
+
+    let mut v = vec![1, 2, 3, 4];
+    keep_even(&mut v);
+    assert_eq!(v, vec![2, 4]);
+
+    let v = &mut v;
+    for_retain!(*v, |&x| { x > 2 });
+    assert_eq!(*v, vec![4]);
+}
+
+#[test]
+fn collect_with_custom_target() {
+    /// counts of each value, in order of first appearance
+    #[derive(Debug, PartialEq)]
+    struct Tally(Vec<(char, usize)>);
+
+    impl std::iter::FromIterator<char> for Tally {
+        fn from_iter<I: IntoIterator<Item = char>>(iter: I) -> Self {
+            let mut tally: Vec<(char, usize)> = vec![];
+            for c in iter {
+                match tally.iter_mut().find(|(k, _)| *k == c) {
+                    Some((_, n)) => *n += 1,
+                    None => tally.push((c, 1)),
+                }
+            }
+            Tally(tally)
+        }
+    }
+
+    let words = ["abc", "bcd"];
+    let tally = for_collect_with!(Tally, {
+        for w in words;
+        for c in w.chars();
+        if c != 'd';
+        c
+    });
+    assert_eq!(tally, Tally(vec![('a', 1), ('b', 2), ('c', 2)]));
+
+    let set = for_collect_with!(std::collections::BTreeSet<_>, {
+        for x in [3, 1, 3];
+        x
+    });
+    assert_eq!(set.into_iter().collect::<Vec<_>>(), [1, 3]);
+}
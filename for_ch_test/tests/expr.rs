@@ -0,0 +1,215 @@
+// the positions are the point of these tests
+#![allow(clippy::unit_arg, clippy::match_single_binding)]
+
+use for_ch::{for_ch, for_format, for_iter, for_string, for_unzip, for_vec, try_for_ch};
+use std::ops::ControlFlow;
+
+fn id<T>(x: T) -> T {
+    x
+}
+
+#[test]
+fn for_ch_in_expression_position() {
+    let mut n = 0;
+    let () = for_ch! {
+        for x in 0..3;
+        n += x;
+    };
+    let () = for_ch! {};
+    id(for_ch! {
+        for x in 0..3;
+        n += x;
+    });
+    match for_ch! { for x in 0..3; n += x; } {
+        () => {}
+    }
+    assert_eq!(n, 9);
+}
+
+#[test]
+fn break_for_ch_with_value() {
+    let first_above = |xs: &[i32], n| -> Option<(usize, i32)> {
+        for_ch! {
+            for i, x in xs.iter().copied() enumerate;
+            if x > n;
+            break 'for_ch Some((i, x));
+        }
+    };
+    assert_eq!(first_above(&[1, 5, 2, 7], 4), Some((1, 5)));
+    assert_eq!(first_above(&[1, 5, 2, 7], 9), None);
+
+    let triple = for_ch! {
+        for x in 1..;
+        for y in 1..x;
+        for z in 0..y {
+            if x + y + z == 6 {
+                break 'for_ch (x, y, z);
+            }
+        }
+    };
+    assert_eq!(triple, (3, 2, 1));
+
+    let mut n = 0;
+    for_ch! {
+        for _ in 0..10;
+        for _ in 0..10;
+        n += 1;
+        if n == 15 {
+            break 'for_ch;
+        }
+    }
+    assert_eq!(n, 15);
+}
+
+#[test]
+fn collecting_in_expression_position() {
+    let v = for_vec! { for x in 0..3; x };
+    assert_eq!(v, [0, 1, 2]);
+    assert_eq!(id(for_string! { for c in "ab".chars(); c }), "ab");
+    assert_eq!(id(for_format! { for x in 0..3; "{}", x }), "012");
+    match for_unzip! { for x in 0..2; (x, -x) } {
+        (xs, ys) => assert_eq!((xs, ys), (vec![0, 1], vec![0, -1])),
+    }
+}
+
+#[test]
+fn lazy_and_try_in_expression_position() {
+    let sum: i32 = id(for_iter! { for x in 0..3; x }).sum();
+    assert_eq!(sum, 3);
+    match id::<Result<(), i32>>(try_for_ch! { for x in 0..3; if x > 5; Err(x)?; }) {
+        Ok(()) => {}
+        Err(x) => panic!("{}", x),
+    }
+}
+
+#[test]
+fn match_arms_and_if_branches() {
+    let mut n = 0;
+    for k in 0..3 {
+        match k {
+            0 => for_ch! { for x in 0..3; n += x; },
+            1 => for_ch! {},
+            _ => for_ch! {
+                for x in 0..k;
+                if x > 0;
+                n += 10 * x;
+            },
+        }
+    }
+    assert_eq!(n, 13);
+
+    let rows = for_vec! {
+        for k in 0..3;
+        match k {
+            0 => for_vec! { for x in 0..2; x },
+            _ => for_vec! { for x in 0..k; x * 10 },
+        }
+    };
+    assert_eq!(rows, vec![vec![0, 1], vec![0], vec![0, 10]]);
+
+    let flag = true;
+    let v = if flag {
+        for_vec! { for x in 0..2; x }
+    } else {
+        Vec::new()
+    };
+    assert_eq!(v, [0, 1]);
+    if flag {
+        for_ch! { for x in 0..2; n += x; }
+    } else {
+        for_ch! { for x in 0..2; n -= x; }
+    }
+    assert_eq!(n, 14);
+}
+
+#[test]
+#[deny(unreachable_code)]
+#[should_panic(expected = "6")]
+fn diverging_chain_without_trailing_break() {
+    fn fail(x: i32) -> ! {
+        for_ch! {
+            let y = x * 2;
+            panic!("{}", y);
+        }
+    }
+    fail(3);
+}
+
+#[test]
+fn bare_break_before_the_first_for() {
+    let mut v = vec![];
+    // the `break` leaves the nest, not the `for stop` around it
+    for stop in [true, false] {
+        for_ch! {
+            if stop {
+                break;
+            }
+            for x in 0..2;
+            v.push(x);
+        }
+    }
+    assert_eq!(v, [0, 1]);
+
+    // and an attributed `else continue` starts the nest over
+    let mut tries = 0;
+    let mut v = vec![];
+    for_ch! {
+        #[allow(unused_parens)]
+        if tries == 2 else continue { tries += 1 };
+        for x in 0..2;
+        v.push(x);
+    }
+    assert_eq!((tries, v), (2, vec![0, 1]));
+}
+
+#[test]
+fn control_flow_clause() {
+    fn check(x: i32) -> ControlFlow<Option<i32>> {
+        if x > 3 {
+            ControlFlow::Break(Some(x * 10))
+        } else {
+            ControlFlow::Continue(())
+        }
+    }
+
+    let mut seen = vec![];
+    let found: Option<i32> = for_ch! {
+        for x in [1, 5, 2, 7];
+        cf check(x);
+        seen.push(x);
+    };
+    assert_eq!(found, Some(50));
+    assert_eq!(seen, [1]);
+
+    seen.clear();
+    let found: Option<i32> = for_ch! {
+        for x in [1, 2];
+        cf check(x);
+        seen.push(x);
+    };
+    assert_eq!(found, None);
+    assert_eq!(seen, [1, 2]);
+
+    let v = for_vec! {
+        for x in 0..;
+        cf if x == 3 { ControlFlow::Break(()) } else { ControlFlow::Continue(()) };
+        x
+    };
+    assert_eq!(v, [0, 1, 2]);
+}
+
+#[test]
+fn cf_as_a_variable() {
+    let mut cf = 0;
+    for_ch! {
+        for x in 0..4;
+        cf += x;
+    }
+    assert_eq!(cf, 6);
+
+    for_ch! {
+        for x in [7, 8];
+        cf = x;
+    }
+    assert_eq!(cf, 8);
+}
@@ -0,0 +1,122 @@
+use for_ch::for_ch;
+
+fn dataset() -> Vec<(u32, Option<u32>)> {
+    (0..10_000u32)
+        .map(|i| (i.wrapping_mul(2_654_435_761) % 1000, i.checked_rem(7)))
+        .collect()
+}
+
+#[test]
+fn for_each_matches_loops() {
+    let data = dataset();
+
+    let mut expected = (0u64, 0usize);
+    for &(x, r) in data.iter().chain(data.iter().rev()) {
+        if x % 3 == 0 {
+            let y = u64::from(x) * 2;
+            if let Some(r) = r {
+                if r > 2 {
+                    expected.0 += y + u64::from(r);
+                    expected.1 += 1;
+                }
+            }
+        }
+    }
+
+    let mut actual = (0u64, 0usize);
+    for_ch! {
+        for &(x, r) in data.iter().chain(data.iter().rev());
+        if x % 3 == 0;
+        let y = u64::from(x) * 2;
+        if let Some(r) = r;
+        if r > 2;
+        actual.0 += y + u64::from(r);
+        actual.1 += 1;
+    }
+    assert_eq!(actual, expected);
+}
+
+#[test]
+fn jumps_keep_the_loop() {
+    let data = dataset();
+
+    let mut taken = vec![];
+    for_ch! {
+        for &(x, _) in &data;
+        if x > 990;
+        if taken.len() == 3 {
+            break;
+        }
+        taken.push(x);
+    }
+    assert_eq!(taken.len(), 3);
+    assert!(taken.iter().all(|&x| x > 990));
+
+    let first_big = || -> Option<u32> {
+        for_ch! {
+            for &(x, _) in &data;
+            if x > 995;
+            return Some(x);
+        }
+        None
+    };
+    assert_eq!(first_big(), data.iter().map(|&(x, _)| x).find(|&x| x > 995));
+}
+
+#[test]
+fn else_err_keeps_the_loop() {
+    fn total(xs: &[Result<u32, String>]) -> Result<u32, String> {
+        let mut total = 0;
+        for_ch! {
+            for x in xs.iter().cloned();
+            if let Ok(y) = x else err;
+            total += y;
+        }
+        Ok(total)
+    }
+
+    assert_eq!(total(&[Ok(1), Ok(2), Ok(3)]), Ok(6));
+    assert_eq!(total(&[Ok(1), Err("bad".into()), Ok(3)]), Err("bad".into()));
+}
+
+#[test]
+fn else_continue_keeps_the_loop() {
+    let mut v = vec![];
+    for_ch! {
+        for x in 0..5;
+        #[allow(unused_parens)]
+        if x % 2 == 0 else continue { v.push(-1) };
+        v.push(x);
+    }
+    assert_eq!(v, [0, -1, 2, -1, 4]);
+}
+
+#[test]
+fn lowering_knob() {
+    let data = dataset();
+    let sum = |lowered: u64| {
+        let expected: u64 = data
+            .iter()
+            .flat_map(|&(x, r)| r.map(|r| u64::from(x + r)))
+            .sum();
+        assert_eq!(lowered, expected);
+    };
+
+    let mut total = 0;
+    for_ch! {
+        #![for_each]
+        for &(x, r) in data.iter().chain(&[]);
+        if let Some(r) = r;
+        total += u64::from(x + r);
+    }
+    sum(total);
+
+    let mut total = 0;
+    for_ch! {
+        #![for_loop]
+        for &(x, r) in data.iter().chain(&[]);
+        if let Some(r) = r;
+        total += u64::from(x + r);
+    }
+    sum(total);
+}
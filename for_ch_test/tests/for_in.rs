@@ -0,0 +1,769 @@
+use for_ch::{for_ch, for_iter};
+
+#[test]
+fn array_by_value() {
+    let mut xs = vec![];
+    for_ch! {
+        for x in [1, 2, 3];
+        xs.push(x);
+    }
+    assert_eq!(xs, vec![1, 2, 3]);
+}
+
+#[test]
+fn array_by_value_zipped() {
+    let mut xs = vec![];
+    for_ch! {
+        for x in [1, 2, 3], for y in 0..;
+        xs.push((x, y));
+    }
+    assert_eq!(xs, vec![(1, 0), (2, 1), (3, 2)]);
+}
+
+#[test]
+fn labeled_zip_break() {
+    let mut xs = vec![];
+    for_ch! {
+        for _ in 0..2;
+        'z: for x in 0..10, for y in 10..;
+        if x == 3 else { xs.push((x, y)); };
+        break 'z;
+    }
+    assert_eq!(
+        xs,
+        vec![(0, 10), (1, 11), (2, 12), (0, 10), (1, 11), (2, 12)]
+    );
+}
+
+#[test]
+fn labeled_zip_continue() {
+    let mut xs = vec![];
+    for_ch! {
+        'z: for x in 0..5, for y in 10..;
+        for z in 0..;
+        if z == 1 else { xs.push((x, y)); };
+        continue 'z;
+    }
+    assert_eq!(xs, vec![(0, 10), (1, 11), (2, 12), (3, 13), (4, 14)]);
+}
+
+#[test]
+#[allow(for_loops_over_fallibles)]
+fn option_source() {
+    let mut xs = vec![];
+    for_ch! {
+        for x in Some(5);
+        xs.push(x);
+    }
+    for_ch! {
+        for x in None::<i32>;
+        xs.push(x);
+    }
+    assert_eq!(xs, vec![5]);
+}
+
+#[test]
+#[allow(for_loops_over_fallibles)]
+fn result_source() {
+    let mut xs = vec![];
+    for_ch! {
+        for x in Ok::<_, ()>(5);
+        xs.push(x);
+    }
+    for_ch! {
+        for x in Err::<i32, _>(());
+        xs.push(x);
+    }
+    assert_eq!(xs, vec![5]);
+}
+
+#[test]
+#[allow(for_loops_over_fallibles)]
+fn option_source_zipped() {
+    let mut xs = vec![];
+    for_ch! {
+        for x in Some(5), for y in 0..10;
+        xs.push((x, y));
+    }
+    assert_eq!(xs, vec![(5, 0)]);
+}
+
+#[test]
+fn refutable_pattern() {
+    let opts: Vec<Option<i32>> = vec![Some(1), None, Some(2)];
+    let mut xs = vec![];
+    for_ch! {
+        for? Some(x) in opts;
+        xs.push(x);
+    }
+    assert_eq!(xs, vec![1, 2]);
+}
+
+#[test]
+fn refutable_pattern_zipped() {
+    let mut xs = vec![];
+    for_ch! {
+        for i in 0.., for? Some(x) in vec![Some(1), None, Some(2)], for? Ok(y) in vec![Ok(3), Ok(4), Err(())];
+        xs.push((i, x, y));
+    }
+    assert_eq!(xs, vec![(0, 1, 3)]);
+}
+
+#[test]
+fn map_adapter() {
+    let mut xs = vec![];
+    for_ch! {
+        for y in [1, 2, 3] map |x| x * 2;
+        xs.push(y);
+    }
+    assert_eq!(xs, vec![2, 4, 6]);
+}
+
+#[test]
+fn map_adapter_match() {
+    #[derive(Clone, Copy)]
+    enum Shape {
+        Circle,
+        Triangle,
+        Square,
+    }
+
+    let shapes = [Shape::Square, Shape::Circle, Shape::Triangle];
+    let mut corners = vec![];
+    for_ch! {
+        for n in shapes map |s| match s {
+            Shape::Circle => 0,
+            Shape::Triangle => 3,
+            Shape::Square => 4,
+        };
+        corners.push(n);
+    }
+    assert_eq!(corners, vec![4, 0, 3]);
+}
+
+#[test]
+fn map_adapter_zipped() {
+    let mut xs = vec![];
+    for_ch! {
+        for y in 0..3 map |x| x * 2 map |x| x + 1, for z in "abc".chars();
+        xs.push((y, z));
+    }
+    assert_eq!(xs, vec![(1, 'a'), (3, 'b'), (5, 'c')]);
+}
+
+#[test]
+fn step_rev_adapters() {
+    let mut xs = vec![];
+    for_ch! {
+        for x in (0..100) step 2 rev;
+        xs.push(x);
+    }
+    assert_eq!(xs, (0..50).rev().map(|x| x * 2).collect::<Vec<_>>());
+    assert_eq!(xs[..3], [98, 96, 94]);
+}
+
+#[test]
+fn stride_ranges() {
+    let (start, end, step) = (1, 10, 3);
+    let mut xs = vec![];
+    for_ch! {
+        for x in start..end by step;
+        xs.push(x);
+    }
+    assert_eq!(xs, [1, 4, 7]);
+
+    xs.clear();
+    for_ch! {
+        for x in start..=end by step;
+        xs.push(x);
+    }
+    assert_eq!(xs, [1, 4, 7, 10]);
+}
+
+#[test]
+#[should_panic]
+fn stride_by_zero() {
+    let step = 0;
+    for_ch! {
+        for x in 0..10 by step;
+        unreachable!("{}", x);
+    }
+}
+
+#[test]
+fn stacked_adapters() {
+    let xs = [10, 20, 30];
+    let mut v = vec![];
+    for_ch! {
+        for i, x in xs rev enumerate;
+        v.push((i, x));
+    }
+    assert_eq!(v, [(0, 30), (1, 20), (2, 10)]);
+
+    v.clear();
+    for_ch! {
+        for i, x in xs enumerate rev map |(i, x)| (i * 2, x + 1);
+        v.push((i, x));
+    }
+    assert_eq!(v, [(4, 31), (2, 21), (0, 11)]);
+}
+
+#[test]
+fn take_adapter() {
+    let mut xs = vec![];
+    for_ch! {
+        for x in std::iter::repeat(0) take 5;
+        for y in (0..) take 2;
+        xs.push(x + y);
+    }
+    assert_eq!(xs, [0, 1].repeat(5));
+}
+
+#[test]
+fn enumerate_zipped() {
+    let mut xs = vec![];
+    for_ch! {
+        for i, x in ['a', 'b', 'c'] enumerate, for y in 10..;
+        xs.push((i, x, y));
+    }
+    assert_eq!(xs, vec![(0, 'a', 10), (1, 'b', 11), (2, 'c', 12)]);
+}
+
+#[test]
+fn str_adapters() {
+    let mut cs = vec![];
+    for_ch! {
+        for c in "héllo" chars filter |c| c.is_alphabetic();
+        cs.push(c);
+    }
+    assert_eq!(cs, vec!['h', 'é', 'l', 'l', 'o']);
+
+    let s = String::from("hi");
+    let mut bs = vec![];
+    for_ch! {
+        for b in s bytes, for i in 0..;
+        bs.push((i, b));
+    }
+    assert_eq!(bs, vec![(0, b'h'), (1, b'i')]);
+
+    let mut ls = vec![];
+    for_ch! {
+        for line in "a\nbc\n" lines;
+        for c in line chars;
+        ls.push((line, c));
+    }
+    assert_eq!(ls, vec![("a", 'a'), ("bc", 'b'), ("bc", 'c')]);
+}
+
+#[test]
+fn flat_map_adapter() {
+    let mut xs = vec![];
+    for_ch! {
+        for y in [1, 2, 3] flat_map |x| vec![x; x], for i in 0..;
+        xs.push((i, y));
+    }
+    assert_eq!(xs, vec![(0, 1), (1, 2), (2, 2), (3, 3), (4, 3), (5, 3)]);
+}
+
+#[derive(Debug, PartialEq, Clone, Copy)]
+enum Foo {
+    A(i32),
+    B,
+    C(i32),
+}
+
+#[test]
+fn if_matches_adapter() {
+    let foos = [Foo::A(1), Foo::B, Foo::C(2), Foo::A(3)];
+    let mut xs = vec![];
+    for_ch! {
+        for x in foos if matches Foo::A(_);
+        xs.push(x);
+    }
+    assert_eq!(xs, vec![Foo::A(1), Foo::A(3)]);
+
+    let mut xs = vec![];
+    for_ch! {
+        for x in foos if matches Foo::B | Foo::C(_), for i in 0..;
+        xs.push((i, x));
+    }
+    assert_eq!(xs, vec![(0, Foo::B), (1, Foo::C(2))]);
+}
+
+#[test]
+fn commas_inside_sources() {
+    fn range(a: i32, b: i32) -> std::ops::Range<i32> {
+        a..b
+    }
+
+    let pairs = [(1, 2), (3, 4)];
+    let mut xs = vec![];
+    for_ch! {
+        for x in pairs.iter().map(|(a, b)| a + b), for y in range(0, 10), for z in [(5, 6), (7, 8)];
+        xs.push((x, y, z));
+    }
+    assert_eq!(xs, vec![(3, 0, (5, 6)), (7, 1, (7, 8))]);
+
+    let mut xs = vec![];
+    for_ch! {
+        for x in pairs.iter() map |&(a, b)| a * b, for (y, z) in std::iter::repeat((0, 1));
+        xs.push((x, y, z));
+    }
+    assert_eq!(xs, vec![(2, 0, 1), (12, 0, 1)]);
+}
+
+#[test]
+fn raw_iterator_zipped_with_vec() {
+    let names = vec!["a", "b", "c"];
+    let mut ys = vec![];
+    for_ch! {
+        for i in (0..).step_by(10), for name in names;
+        ys.push((i, name));
+    }
+    assert_eq!(ys, vec![(0, "a"), (10, "b"), (20, "c")]);
+}
+
+#[test]
+fn zip_checked_equal_lengths() {
+    let xs = [1, 2, 3];
+    let mut v = vec![];
+    for_ch! {
+        zip_checked for x in xs, for y in xs.iter().rev(), for z in 0..3;
+        v.push((x, *y, z));
+    }
+    assert_eq!(v, [(1, 3, 0), (2, 2, 1), (3, 1, 2)]);
+
+    // without it, zipping stops at the shortest
+    let mut v = vec![];
+    for_ch! {
+        for x in 0..3, for y in 0..2;
+        v.push((x, y));
+    }
+    assert_eq!(v, [(0, 0), (1, 1)]);
+}
+
+#[test]
+#[should_panic(expected = "zip_checked: the zipped items have different lengths")]
+fn zip_checked_unequal_lengths() {
+    for_ch! {
+        zip_checked for x in 0..3, for y in [1, 2];
+        unreachable!("{} {}", x, y);
+    }
+}
+
+#[test]
+fn zip_or_default() {
+    let mut xs = vec![];
+    for_ch! {
+        for x in [1, 2, 3], for y in [10] or default;
+        xs.push((x, y));
+    }
+    assert_eq!(xs, vec![(1, 10), (2, 0), (3, 0)]);
+}
+
+#[test]
+fn implicit_indices() {
+    let mut xs = vec![];
+    for_ch! {
+        for x in ['a', 'b'];
+        let outer = $i;
+        for? Some(y) in [Some('x'), None, Some('z')];
+        xs.push((outer, $i0, $i1, $i, x, y));
+    }
+    assert_eq!(
+        xs,
+        vec![
+            (0, 0, 0, 0, 'a', 'x'),
+            (0, 0, 2, 2, 'a', 'z'),
+            (1, 1, 0, 0, 'b', 'x'),
+            (1, 1, 2, 2, 'b', 'z'),
+        ]
+    );
+
+    let it = for_ch::for_iter! {
+        for x in 10..12;
+        for y in 0..x - 8;
+        ($i0, $i, y)
+    };
+    assert_eq!(
+        it.collect::<Vec<_>>(),
+        vec![(0, 0, 0), (0, 1, 1), (1, 0, 0), (1, 1, 1), (1, 2, 2)]
+    );
+}
+
+#[test]
+fn array_patterns() {
+    let pairs: Vec<[i32; 2]> = vec![[1, 2], [3, 4]];
+    let mut xs = vec![];
+    for_ch! {
+        for [a, b] in pairs.clone();
+        xs.push(a * b);
+    }
+    assert_eq!(xs, vec![2, 12]);
+
+    let mut ys = vec![];
+    for_ch! {
+        for i in 0.., for [a, b] in pairs;
+        ys.push((i, a, b));
+    }
+    assert_eq!(ys, vec![(0, 1, 2), (1, 3, 4)]);
+}
+
+#[test]
+fn repeat_times() {
+    let mut tries = 0;
+    let mut log = vec![];
+    for_ch! {
+        for x in ['a', 'b'];
+        repeat 3 times;
+        tries += 1;
+        log.push((x, $i));
+    }
+    assert_eq!(tries, 6);
+    assert_eq!(log[..3], [('a', 0), ('a', 1), ('a', 2)]);
+}
+
+#[test]
+fn zip_reference_sources() {
+    let a: Vec<i32> = vec![1, 2, 3];
+    let b: Vec<i32> = vec![4, 5, 6];
+    let mut dots = 0;
+    for_ch! {
+        for x in &a, for y in &b;
+        let (x, y): (&i32, &i32) = (x, y);
+        dots += x * y;
+    }
+    assert_eq!(dots, 32);
+    assert_eq!((a.len(), b.len()), (3, 3));
+}
+
+#[test]
+fn expression_sources() {
+    let flag = true;
+    let mut v = vec![];
+    for_ch! {
+        for x in if flag { 0..2 } else { 5..9 };
+        for y in match x { 0 => vec!['a'], _ => vec!['b', 'c'] };
+        for z in { let n = x + 1; 0..n };
+        v.push((x, y, z));
+    }
+    assert_eq!(
+        v,
+        [
+            (0, 'a', 0),
+            (1, 'b', 0),
+            (1, 'b', 1),
+            (1, 'c', 0),
+            (1, 'c', 1)
+        ]
+    );
+
+    let mut v = vec![];
+    for_ch! {
+        for x in (if flag { [1, 2] } else { [3, 4] }), for y in match flag { true => 10..12, false => 0..0 };
+        for z in { [x] } map |z| z * y, for w in if flag { "ab" } else { "" } chars;
+        v.push((z, w));
+    }
+    assert_eq!(v, [(10, 'a'), (22, 'a')]);
+}
+
+#[test]
+fn dedup_by_key_adapter() {
+    struct Record {
+        id: u32,
+        name: &'static str,
+    }
+    let mut records = vec![
+        Record { id: 1, name: "a" },
+        Record { id: 1, name: "b" },
+        Record { id: 2, name: "c" },
+        Record { id: 1, name: "d" },
+        Record { id: 1, name: "e" },
+        Record { id: 3, name: "f" },
+    ];
+
+    let mut names = vec![];
+    for_ch! {
+        for r in &records dedup_by_key |r| r.id;
+        names.push(r.name);
+    }
+    assert_eq!(names, ["a", "c", "d", "f"]);
+
+    // a key fn of the same name isn't shadowed by the expansion
+    fn dedup_by_key(r: &&Record) -> u32 {
+        r.id
+    }
+    let mut names = vec![];
+    for_ch! {
+        for r in &records dedup_by_key |r| dedup_by_key(r), for s in &records dedup_by_key dedup_by_key;
+        names.push((r.name, s.name));
+    }
+    assert_eq!(names, [("a", "a"), ("c", "c"), ("d", "d"), ("f", "f")]);
+
+    // it's a plain `Filter`, so `rev` can follow
+    let mut names = vec![];
+    for_ch! {
+        for r in &records dedup_by_key |r| r.id rev;
+        names.push(r.name);
+    }
+    assert_eq!(names, ["f", "e", "c", "b"]);
+
+    records.sort_by_key(|r| r.id);
+    let mut names = vec![];
+    for_ch! {
+        for r in records dedup_by_key |r| r.id;
+        names.push(r.name);
+    }
+    assert_eq!(names, ["a", "c", "f"]);
+}
+
+#[test]
+fn field_sources() {
+    struct Container {
+        items: Vec<String>,
+        weights: Vec<u32>,
+        name: String,
+    }
+    let new = || Container {
+        items: vec!["a".to_string(), "b".to_string()],
+        weights: vec![1, 2],
+        name: "c".to_string(),
+    };
+
+    let container = new();
+    let mut seen = vec![];
+    for_ch! {
+        for item in &container.items;
+        seen.push(item.clone());
+    }
+    for_ch! {
+        for item in container.items;
+        seen.push(item);
+    }
+    // only the field was moved
+    seen.push(container.name);
+    assert_eq!(seen, ["a", "b", "a", "b", "c"]);
+
+    let mut container = new();
+    for_ch! {
+        for w in &mut container.weights;
+        *w += container.name.len() as u32;
+    }
+    assert_eq!(container.weights, [2, 3]);
+    container.weights = vec![1, 2];
+
+    let mut pairs = vec![];
+    for_ch! {
+        for item in &container.items, for w in &mut container.weights;
+        *w *= 10;
+        pairs.push((item.clone(), *w));
+    }
+    for_ch! {
+        for item in container.items, for w in container.weights;
+        pairs.push((item, w));
+    }
+    assert_eq!(
+        pairs,
+        [
+            ("a".to_string(), 10),
+            ("b".to_string(), 20),
+            ("a".to_string(), 10),
+            ("b".to_string(), 20),
+        ]
+    );
+    assert_eq!(container.name, "c");
+}
+
+#[test]
+fn zip_owned_with_borrowed() {
+    let owned = vec![1, 2, 3];
+    let borrowed = vec![10, 20, 30, 40];
+    let mut sums = vec![];
+    for_ch! {
+        for x in owned, for y in &borrowed;
+        let (x, y): (i32, &i32) = (x, y);
+        sums.push(x + y);
+    }
+    assert_eq!(sums, [11, 22, 33]);
+
+    let owned = vec![1, 2, 3];
+    sums.clear();
+    for_ch! {
+        for y in &borrowed, for x in owned, for z in borrowed.iter().rev();
+        let (x, y, z): (i32, &i32, &i32) = (x, y, z);
+        sums.push(x + y + z);
+    }
+    assert_eq!(sums, [51, 52, 53]);
+    assert_eq!(borrowed.len(), 4);
+}
+
+#[test]
+fn typed_sources() {
+    let xs = vec![1, 2, 3];
+    let mut total = 0u64;
+    for_ch! {
+        for x in {
+            let it: std::vec::IntoIter<u32> = xs.clone().into_iter();
+            it
+        };
+        total += u64::from(x);
+    }
+    for_ch! {
+        for x in <Vec<u32> as IntoIterator>::into_iter(xs);
+        for y in std::iter::once::<u64>(10);
+        total += u64::from(x) * y;
+    }
+    for_ch! {
+        for x in "4 5".split(' ').map(str::parse::<u64>);
+        total += x.unwrap();
+    }
+    assert_eq!(total, 6 + 60 + 9);
+}
+
+#[test]
+fn flat_zip() {
+    let (a, b, c) = ([1, 2, 3], ['x', 'y', 'z'], ["p", "q"]);
+    let mut nested = vec![];
+    for_ch! {
+        for x in a, for y in b, for z in c;
+        nested.push((x, y, z));
+    }
+    let mut flat = vec![];
+    for_ch! {
+        flat for x in a, for y in b, for z in c;
+        flat.push((x, y, z));
+    }
+    assert_eq!(flat, [(1, 'x', "p"), (2, 'y', "q")]);
+    assert_eq!(flat, nested);
+
+    let mut v = vec![];
+    for_ch! {
+        flat zip_checked for i, x in a.iter() enumerate, for? Some(y) in [None, Some(5), Some(6)];
+        v.push((i, *x, y));
+    }
+    assert_eq!(v, [(1, 2, 5), (2, 3, 6)]);
+
+    let v: Vec<_> = for_iter! {
+        flat for x in a, for y in b, for z in c;
+        (x, y, z)
+    }
+    .collect();
+    assert_eq!(v, flat);
+}
+
+#[test]
+fn drain_a_channel() {
+    let (tx, rx) = std::sync::mpsc::channel();
+    let sender = std::thread::spawn(move || {
+        for i in 0..4 {
+            tx.send(i).unwrap();
+            std::thread::sleep(std::time::Duration::from_millis(1));
+        }
+        // dropping `tx` ends the loop below
+    });
+
+    let mut received = vec![];
+    for_ch! {
+        for msg in rx;
+        if msg != 2;
+        received.push(msg * 10);
+    }
+    assert_eq!(received, [0, 10, 30]);
+    sender.join().unwrap();
+
+    let (tx, rx) = std::sync::mpsc::channel();
+    for i in 0..3 {
+        tx.send(i).unwrap();
+    }
+    drop(tx);
+    let mut borrowed = vec![];
+    for_ch! {
+        for msg in &rx;
+        borrowed.push(msg);
+    }
+    assert_eq!(borrowed, [0, 1, 2]);
+}
+
+#[test]
+fn zip_with_adapter() {
+    let mut sums = vec![];
+    for_ch! {
+        for z in 0..4 zip_with 10..13 via |x, y| x + y;
+        sums.push(z);
+    }
+    assert_eq!(sums, [10, 12, 14]);
+
+    let names = ["a", "b"];
+    let mut labels = vec![];
+    for_ch! {
+        for label in (1..) zip_with &names via |i, name| format!("{}{}", name, i) take 1;
+        labels.push(label);
+    }
+    assert_eq!(labels, ["a1"]);
+
+    fn zip_with(x: i32, y: i32) -> i32 {
+        x * y
+    }
+    let mut products = vec![];
+    for_ch! {
+        for p in 1..4 zip_with 1..4 via |x, y| zip_with(x, y), for q in 1..4 zip_with 2..5 via zip_with;
+        products.push((p, q));
+    }
+    assert_eq!(products, [(1, 2), (4, 6), (9, 12)]);
+
+    let pairs = [(1, 'a'), (2, 'b')];
+    let mut keys = vec![];
+    for_ch! {
+        for k in &pairs zip_with ["x", "y"] via |p: &(i32, char), s| format!("{}{}{}", p.0, p.1, s);
+        keys.push(k);
+    }
+    assert_eq!(keys, ["1ax", "2by"]);
+}
+
+#[test]
+fn grid_source() {
+    let cells = [['a', 'b', 'c'], ['d', 'e', 'f']];
+    let mut visited = vec![];
+    for_ch! {
+        for (r, c) in for_ch::grid(cells.len(), cells[0].len());
+        visited.push((r, c, cells[r][c]));
+    }
+    assert_eq!(
+        visited,
+        [
+            (0, 0, 'a'),
+            (0, 1, 'b'),
+            (0, 2, 'c'),
+            (1, 0, 'd'),
+            (1, 1, 'e'),
+            (1, 2, 'f'),
+        ]
+    );
+
+    let mut diagonal = vec![];
+    for_ch! {
+        for r, c in ::for_ch::grid(3, 3) filter |&(r, c)| r == c;
+        diagonal.push((r, c));
+    }
+    assert_eq!(diagonal, [(0, 0), (1, 1), (2, 2)]);
+
+    // rows and cols are each evaluated once, in order
+    let mut evaluated = vec![];
+    let mut cells = 0;
+    for_ch! {
+        for _ in for_ch::grid({ evaluated.push("rows"); 2 }, { evaluated.push("cols"); 3 });
+        cells += 1;
+    }
+    assert_eq!((evaluated, cells), (vec!["rows", "cols"], 6));
+
+    // a `grid` of your own is still an ordinary call
+    fn grid(rows: usize, cols: usize) -> Vec<usize> {
+        vec![rows * cols; 2]
+    }
+    let mut areas = vec![];
+    for_ch! {
+        for area in grid(2, 3);
+        areas.push(area);
+    }
+    assert_eq!(areas, [6, 6]);
+}
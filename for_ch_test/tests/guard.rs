@@ -0,0 +1,291 @@
+use for_ch::{for_ch, for_vec};
+
+#[test]
+fn guard_let_flows_into_following_clauses() {
+    let xs = vec![Some(1), None, Some(3)];
+    let mut ys = vec![];
+    for_ch! {
+        for x in xs;
+        guard let Some(x) = x else { continue };
+        for y in 0..x;
+        ys.push((x, y));
+    }
+    assert_eq!(ys, vec![(1, 0), (3, 0), (3, 1), (3, 2)]);
+}
+
+#[test]
+fn guard_let_break() {
+    let mut ys = vec![];
+    for_ch! {
+        for x in [Ok(1), Ok(2), Err(()), Ok(4)];
+        guard let Ok(x) = x else { break };
+        ys.push(x);
+    }
+    assert_eq!(ys, vec![1, 2]);
+}
+
+#[test]
+#[allow(clippy::collapsible_if)]
+fn coalesced_guards_short_circuit() {
+    fn check(log: &mut Vec<(i32, u8)>, x: i32, id: u8, ok: bool) -> bool {
+        log.push((x, id));
+        ok
+    }
+
+    let mut coalesced = vec![];
+    let mut hits = vec![];
+    for_ch! {
+        for x in 0..13;
+        if check(&mut coalesced, x, 0, x % 2 == 0);
+        if check(&mut coalesced, x, 1, x % 3 == 0);
+        if let Some(y) = Some(x * 10);
+        if check(&mut coalesced, x, 2, y > 0);
+        hits.push(x);
+    }
+
+    let mut nested = vec![];
+    let mut nested_hits = vec![];
+    for x in 0..13 {
+        if check(&mut nested, x, 0, x % 2 == 0) {
+            if check(&mut nested, x, 1, x % 3 == 0) {
+                if let Some(y) = Some(x * 10) {
+                    if check(&mut nested, x, 2, y > 0) {
+                        nested_hits.push(x);
+                    }
+                }
+            }
+        }
+    }
+
+    assert_eq!(coalesced, nested);
+    assert_eq!(hits, nested_hits);
+    assert_eq!(hits, vec![6, 12]);
+}
+
+#[test]
+fn if_let_at_binding() {
+    let mut ys = vec![];
+    for_ch! {
+        for e in [Some(1), None, Some(3)];
+        if let all @ Some(inner) = e;
+        ys.push((all, inner));
+    }
+    assert_eq!(ys, vec![(Some(1), 1), (Some(3), 3)]);
+}
+
+#[test]
+fn guard_else_if_chain() {
+    let mut log = vec![];
+    for_ch! {
+        for x in 0..6;
+        if x % 3 == 0 else if x % 3 == 1 {
+            log.push(("one", x));
+        } else if x == 2 {
+            log.push(("two", x));
+        } else {
+            log.push(("other", x));
+        };
+        log.push(("zero", x));
+    }
+    assert_eq!(
+        log,
+        vec![
+            ("zero", 0),
+            ("one", 1),
+            ("two", 2),
+            ("zero", 3),
+            ("one", 4),
+            ("other", 5),
+        ]
+    );
+}
+
+#[test]
+fn membership_guard() {
+    let set: std::collections::HashSet<_> = [2, 3, 5, 7].iter().copied().collect();
+    let mut ys = vec![];
+    for_ch! {
+        for x in 0..10;
+        if x in set;
+        if x in [1, 2, 3];
+        ys.push(x);
+    }
+    assert_eq!(ys, vec![2, 3]);
+}
+
+#[test]
+fn if_let_tuple_bindings_reach_later_clauses() {
+    let mut ys = vec![];
+    for_ch! {
+        for pair in [(2, Some(0)), (3, None), (3, Some(1))];
+        if let (a, Some(b)) = pair;
+        for a in 0..a;
+        if a != b;
+        ys.push((a, b));
+    }
+    assert_eq!(ys, vec![(1, 0), (0, 1), (2, 1)]);
+}
+
+#[test]
+fn let_chain_guard_binds_into_rest() {
+    let mut ys = vec![];
+    for_ch! {
+        for (a, b) in [(Some(1), Ok(2)), (None, Ok(3)), (Some(4), Err(())), (Some(0), Ok(5))];
+        if let Some(x) = a && x > 0 && let Ok(y) = b;
+        for z in 0..x;
+        ys.push((x, y, z));
+    }
+    assert_eq!(ys, vec![(1, 2, 0)]);
+}
+
+#[test]
+fn if_let_with_match_guard() {
+    let mut ys = vec![];
+    for_ch! {
+        for a in [Some(1), None, Some(-2), Some(3)];
+        if let Some(x) = a if x > 0;
+        ys.push(x);
+    }
+    assert_eq!(ys, vec![1, 3]);
+}
+
+#[test]
+fn is_guard() {
+    let names = [String::from("a"), String::from("bb"), String::from("ccc")];
+    let mut ys = vec![];
+    for_ch! {
+        for (i, name) in names.iter().enumerate();
+        if i is 0 | 2;
+        if name.len() is 1..=2 | 5;
+        ys.push(name.clone());
+    }
+    assert_eq!(ys, vec!["a"]);
+
+    let mut zs = vec![];
+    for_ch! {
+        for x in 0..10;
+        if x is 1 | 4 | 9;
+        zs.push(x);
+    }
+    assert_eq!(zs, vec![1, 4, 9]);
+}
+
+#[test]
+fn let_else_breaks_the_whole_chain() {
+    let rows = [[Some(1), Some(2)], [Some(3), None], [Some(5), Some(6i32)]];
+    let mut seen = vec![];
+    for_ch! {
+        for row in rows;
+        for cell in row;
+        let Some(x) = cell else { break 'for_ch };
+        guard let Some(y) = x.checked_mul(10) else { break 'for_ch };
+        seen.push(y);
+    }
+    assert_eq!(seen, vec![10, 20, 30]);
+
+    let built = for_vec! {
+        for row in rows;
+        for cell in row;
+        let Some(x) = cell else { break 'for_ch };
+        x
+    };
+    assert_eq!(built, vec![1, 2, 3]);
+}
+
+#[test]
+fn guard_else_continue() {
+    let mut skipped = vec![];
+    let pairs = for_vec! {
+        for x in 0..3;
+        for y in 0..3;
+        if x != y else continue {
+            skipped.push(x);
+        };
+        (x, y)
+    };
+    assert_eq!(skipped, vec![0, 1, 2]);
+    assert_eq!(pairs, vec![(0, 1), (0, 2), (1, 0), (1, 2), (2, 0), (2, 1)]);
+}
+
+#[test]
+fn if_let_binds_the_next_source() {
+    let mut xs = vec![];
+    for opt in [Some(vec![1, 2]), None, Some(vec![3])] {
+        for_ch! {
+            if let Some(inner) = opt;
+            for x in inner;
+            xs.push(x);
+        }
+    }
+    assert_eq!(xs, [1, 2, 3]);
+
+    let opts = [None, Some(vec![4, 5])];
+    let v = for_vec! {
+        for opt in &opts;
+        if let Some(inner) = opt;
+        for x in inner;
+        *x
+    };
+    assert_eq!(v, [4, 5]);
+}
+
+#[test]
+fn leading_vert_or_patterns() {
+    enum Shape {
+        Square(u32),
+        Circle(u32),
+        Empty,
+    }
+    let shapes = [Shape::Square(2), Shape::Empty, Shape::Circle(5)];
+
+    let sizes = for_vec! {
+        for s in &shapes;
+        if let | Shape::Square(n) | Shape::Circle(n) = s;
+        n * 10
+    };
+    assert_eq!(sizes, [20, 50]);
+
+    let mut sizes = vec![];
+    for_ch! {
+        for s in &shapes;
+        if let | Shape::Square(n) | Shape::Circle(n) = s else {
+            sizes.push(0);
+        };
+        sizes.push(*n);
+    }
+    assert_eq!(sizes, [2, 0, 5]);
+
+    sizes.clear();
+    for_ch! {
+        for s in &shapes;
+        let | Shape::Square(n) | Shape::Circle(n) = s else { continue };
+        sizes.push(*n);
+    }
+    assert_eq!(sizes, [2, 5]);
+}
+
+#[test]
+fn unless_is_the_inverse_of_if() {
+    let pairs = [(1, 2), (3, 3), (0, 5), (4, 0)];
+    let kept = for_vec! {
+        for (a, b) in pairs;
+        if a > 0 && b > 0;
+        (a, b)
+    };
+    let skipped = for_vec! {
+        for (a, b) in pairs;
+        unless a > 0 && b > 0;
+        (a, b)
+    };
+    assert_eq!(kept, [(1, 2), (3, 3)]);
+    assert_eq!(skipped, [(0, 5), (4, 0)]);
+
+    // the whole condition is negated, not just its first operand
+    let v = for_vec! {
+        for x in 0..6;
+        unless x % 2 == 0 || x == 3;
+        unless (x > 4);
+        x
+    };
+    assert_eq!(v, [1]);
+}
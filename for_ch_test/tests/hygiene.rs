@@ -0,0 +1,29 @@
+use for_ch::{for_ch, for_string, for_vec};
+
+#[test]
+fn user_accumulator_names() {
+    let __acc = 10;
+    let __v = 20;
+    let v = for_vec! {
+        for x in 0..3;
+        x + __acc + __v
+    };
+    assert_eq!(v, [30, 31, 32]);
+
+    let s = for_string! {
+        for _ in 0..__acc;
+        'a'
+    };
+    assert_eq!(s.len(), 10);
+}
+
+#[test]
+fn user_placeholder_names() {
+    let __item0 = 1;
+    let mut xs = vec![];
+    for_ch! {
+        for? Some(x) in [Some(1), None, Some(2)];
+        xs.push(x + __item0);
+    }
+    assert_eq!(xs, vec![2, 3]);
+}
@@ -0,0 +1,100 @@
+use for_ch::{for_ch, for_iter, for_tuples};
+
+#[test]
+fn iter_nested() {
+    let it = for_iter! {
+        for x in 0..3;
+        if x != 1;
+        for y in 0..x;
+        (x, y)
+    };
+    assert_eq!(it.collect::<Vec<_>>(), [(2, 0), (2, 1)]);
+}
+
+#[test]
+fn iter_is_lazy() {
+    let mut it = for_iter! {
+        for x in 0u32..;
+        if let Some(y) = x.checked_mul(2);
+        y
+    };
+    assert_eq!(it.next(), Some(0));
+    assert_eq!(it.next(), Some(2));
+}
+
+#[test]
+fn iter_borrows_through_references() {
+    let ys = vec![String::from("a"), String::from("b")];
+    let ys = &ys;
+    let v: Vec<String> = for_iter! {
+        for x in 0..2;
+        for y in ys;
+        format!("{}{}", y, x)
+    }
+    .collect();
+    assert_eq!(v, ["a0", "b0", "a1", "b1"]);
+}
+
+#[test]
+fn filter_adapter_matches_guard() {
+    let xs = [-2, 3, -1, 4];
+
+    let mut by_adapter = vec![];
+    let mut by_guard = vec![];
+    for_ch! {
+        for x in xs filter |x| *x > 0;
+        by_adapter.push(x);
+    }
+    for_ch! {
+        for x in xs;
+        if x > 0;
+        by_guard.push(x);
+    }
+    assert_eq!(by_adapter, by_guard);
+    assert_eq!(by_adapter, [3, 4]);
+
+    let by_adapter: Vec<_> = for_iter! {
+        for x in xs filter |x| *x > 0;
+        x
+    }
+    .collect();
+    let by_guard: Vec<_> = for_iter! {
+        for x in xs;
+        if x > 0;
+        x
+    }
+    .collect();
+    assert_eq!(by_adapter, by_guard);
+}
+
+#[test]
+fn tuples_of_bindings() {
+    let xs = [1, 2, 3];
+    let ys = &[Some(1), None, Some(4)];
+    let tuples: Vec<_> = for_tuples! {
+        for x in xs;
+        for (j, y) in ys.iter().enumerate();
+        if let Some(z) = y;
+        if (x + z) % 2 == 1;
+    }
+    .collect();
+
+    let mut expected = vec![];
+    for x in xs {
+        for (j, y) in ys.iter().enumerate() {
+            if let Some(z) = y {
+                if (x + z) % 2 == 1 {
+                    expected.push((x, j, y, z));
+                }
+            }
+        }
+    }
+    assert_eq!(tuples, expected);
+
+    let single: Vec<_> = for_tuples! {
+        for x in 0..3;
+        if x != 1;
+    }
+    .collect();
+    assert_eq!(single, [(0,), (2,)]);
+}
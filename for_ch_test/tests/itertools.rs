@@ -0,0 +1,151 @@
+#![cfg(feature = "itertools")]
+
+use for_ch::for_vec;
+
+struct Record {
+    k: u32,
+    v: u32,
+}
+
+#[test]
+fn chunk_by_consecutive_keys() {
+    let records = vec![
+        Record { k: 1, v: 1 },
+        Record { k: 1, v: 2 },
+        Record { k: 2, v: 3 },
+        Record { k: 1, v: 4 },
+    ];
+    let v = for_vec! {
+        for (k, group) in chunk_by(|r: &Record| r.k, records);
+        for r in group;
+        (k, r.v)
+    };
+    assert_eq!(v, [(1, 1), (1, 2), (2, 3), (1, 4)]);
+}
+
+#[test]
+fn chunk_by_group_sums() {
+    let xs = [1, 1, 2, 2, 2, 3];
+    let v = for_vec! {
+        for (k, group) in chunk_by(|x: &i32| *x, xs);
+        (k, group.count())
+    };
+    assert_eq!(v, [(1, 2), (2, 3), (3, 1)]);
+}
+
+#[test]
+fn product_all_combinations() {
+    let v = for_vec! {
+        for (x, y, z) in product 0..2, ['a', 'b'], [true, false];
+        (x, y, z)
+    };
+    assert_eq!(
+        v,
+        [
+            (0, 'a', true),
+            (0, 'a', false),
+            (0, 'b', true),
+            (0, 'b', false),
+            (1, 'a', true),
+            (1, 'a', false),
+            (1, 'b', true),
+            (1, 'b', false),
+        ]
+    );
+}
+
+#[test]
+fn product_zipped() {
+    let v = for_vec! {
+        for (x, y) in product 0..2, 0..2, for i in 0..;
+        (i, x, y)
+    };
+    assert_eq!(v, [(0, 0, 0), (1, 0, 1), (2, 1, 0), (3, 1, 1)]);
+}
+
+#[test]
+fn product_as_variable() {
+    let product = [1, 2];
+    let v = for_vec! {
+        for x in product;
+        x
+    };
+    assert_eq!(v, [1, 2]);
+}
+
+#[test]
+fn zip_eq_equal_lengths() {
+    let v = for_vec! {
+        zip_eq for x in 0..3, for y in ['a', 'b', 'c'];
+        (x, y)
+    };
+    assert_eq!(v, [(0, 'a'), (1, 'b'), (2, 'c')]);
+}
+
+#[test]
+#[should_panic]
+fn zip_eq_unequal_lengths() {
+    for_vec! {
+        zip_eq for x in 0..3, for y in ['a', 'b'];
+        (x, y)
+    };
+}
+
+#[test]
+fn pairwise_source() {
+    let xs = [1, 2, 3, 4];
+    let v = for_vec! {
+        for (prev, cur) in pairwise xs;
+        (prev, cur)
+    };
+    assert_eq!(v, [(1, 2), (2, 3), (3, 4)]);
+
+    let one = [1];
+    let v = for_vec! {
+        for pair in pairwise one;
+        pair
+    };
+    assert!(v.is_empty());
+}
+
+#[test]
+fn sorted_adapters() {
+    let xs = [3, 1, 4, 1, 5, 9, 2, 6];
+    let v = for_vec! {
+        for x in xs sorted;
+        x
+    };
+    assert_eq!(v, [1, 1, 2, 3, 4, 5, 6, 9]);
+
+    let v = for_vec! {
+        for x in xs sorted_by |a, b| b.cmp(a) take 3;
+        x
+    };
+    assert_eq!(v, [9, 6, 5]);
+
+    let words = ["pear", "fig", "banana", "kiwi"];
+    let v = for_vec! {
+        for (i, w) in words sorted_by_key |w| w.len() enumerate;
+        (i, w)
+    };
+    assert_eq!(v, [(0, "fig"), (1, "pear"), (2, "kiwi"), (3, "banana")]);
+}
+
+#[test]
+fn tee_clause() {
+    let v = for_vec! {
+        tee (a, b) = vec![1, 4, 9, 16];
+        for x in a, for y in b.skip(1);
+        y - x
+    };
+    assert_eq!(v, [3, 5, 7]);
+
+    let v = for_vec! {
+        for n in 1..4;
+        tee (evens, odds) = 0..n * 2;
+        let evens: Vec<_> = evens.filter(|x| x % 2 == 0).collect();
+        for odd in odds.filter(|x| x % 2 == 1);
+        (evens.len(), odd)
+    };
+    assert_eq!(v, [(1, 1), (2, 1), (2, 3), (3, 1), (3, 3), (3, 5)]);
+}
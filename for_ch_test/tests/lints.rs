@@ -0,0 +1,34 @@
+// the expansions must not light up lints in the caller's crate, which the
+// clippy run over the tests checks
+#![warn(
+    clippy::into_iter_on_ref,
+    clippy::explicit_into_iter_loop,
+    clippy::explicit_iter_loop
+)]
+
+use for_ch::{for_ch, for_iter, for_vec};
+
+#[test]
+fn zip_over_references() {
+    let xs: Vec<i32> = vec![1, 2, 3];
+    let mut ys: Vec<i32> = vec![10, 20, 30];
+    let mut v = vec![];
+    for_ch! {
+        for x in &xs, for y in &mut ys;
+        *y += x;
+        v.push(*y);
+    }
+    assert_eq!(v, [11, 22, 33]);
+
+    let v = for_vec! {
+        for x in &xs map |x| x * 2, for y in &ys;
+        x + y
+    };
+    assert_eq!(v, [13, 26, 39]);
+
+    let it = for_iter! {
+        for x in &xs, for y in &ys;
+        x * y
+    };
+    assert_eq!(it.sum::<i32>(), 11 + 44 + 99);
+}
@@ -0,0 +1,67 @@
+#![cfg(feature = "nightly")]
+#![cfg_attr(
+    feature = "nightly",
+    feature(box_patterns, coroutines, coroutine_trait, stmt_expr_attributes)
+)]
+
+use for_ch::{for_ch, for_vec};
+use std::ops::{Coroutine, CoroutineState};
+use std::pin::Pin;
+
+enum Tree {
+    Leaf(i32),
+    Node(Box<Tree>, Box<Tree>),
+}
+
+#[test]
+fn box_patterns_in_if_let() {
+    let trees = vec![
+        Tree::Leaf(1),
+        Tree::Node(Box::new(Tree::Leaf(2)), Box::new(Tree::Leaf(3))),
+        Tree::Node(
+            Box::new(Tree::Node(Box::new(Tree::Leaf(4)), Box::new(Tree::Leaf(5)))),
+            Box::new(Tree::Leaf(6)),
+        ),
+    ];
+
+    let lefts = for_vec! {
+        for tree in &trees;
+        if let Tree::Node(box Tree::Leaf(l), _) = tree;
+        *l
+    };
+    assert_eq!(lefts, vec![2]);
+
+    let mut boxed = vec![];
+    for_ch! {
+        for b in [Box::new(7), Box::new(8)];
+        if let box x = b;
+        if x > 7;
+        boxed.push(x);
+    }
+    assert_eq!(boxed, vec![8]);
+}
+
+#[test]
+fn yield_in_a_coroutine() {
+    let mut coroutine = #[coroutine]
+    || {
+        for_ch! {
+            for x in 0..6;
+            if x % 2 == 0;
+            yield x * 10;
+        }
+        "done"
+    };
+
+    let mut yielded = vec![];
+    loop {
+        match Pin::new(&mut coroutine).resume(()) {
+            CoroutineState::Yielded(x) => yielded.push(x),
+            CoroutineState::Complete(done) => {
+                assert_eq!(done, "done");
+                break;
+            }
+        }
+    }
+    assert_eq!(yielded, [0, 20, 40]);
+}
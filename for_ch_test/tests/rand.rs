@@ -0,0 +1,32 @@
+#![cfg(feature = "rand")]
+
+use for_ch::for_sample;
+use rand::{rngs::StdRng, SeedableRng};
+
+#[test]
+fn sample_is_reproducible_with_a_seed() {
+    let sample = |seed| {
+        let mut rng = StdRng::seed_from_u64(seed);
+        for_sample!(3, &mut rng, {
+            for x in 0..10;
+            for y in 0..10;
+            if (x + y) % 2 == 0;
+            (x, y)
+        })
+    };
+
+    let v = sample(42);
+    assert_eq!(v.len(), 3);
+    assert!(v.iter().all(|(x, y)| (x + y) % 2 == 0));
+    assert_eq!(v, sample(42));
+}
+
+#[test]
+fn sample_keeps_everything_when_short() {
+    let mut v = for_sample!(10, {
+        for x in 0..4;
+        x
+    });
+    v.sort_unstable();
+    assert_eq!(v, [0, 1, 2, 3]);
+}
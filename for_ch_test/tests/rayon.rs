@@ -0,0 +1,56 @@
+#![cfg(feature = "rayon")]
+
+use for_ch::{for_vec, par_for_vec, scope_for_ch};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Mutex;
+
+#[test]
+fn scope_spawns_outer_iterations() {
+    let pairs = Mutex::new(vec![]);
+    let tasks = AtomicUsize::new(0);
+    let (pairs_ref, tasks_ref) = (&pairs, &tasks);
+    scope_for_ch! {
+        for x in 0..4;
+        tasks_ref.fetch_add(1, Ordering::Relaxed);
+        for y in 0..x;
+        pairs_ref.lock().unwrap().push((x, y));
+    }
+
+    let mut pairs = pairs.into_inner().unwrap();
+    pairs.sort_unstable();
+    assert_eq!(pairs, vec![(1, 0), (2, 0), (2, 1), (3, 0), (3, 1), (3, 2)]);
+    assert_eq!(tasks.load(Ordering::Relaxed), 4);
+}
+
+#[test]
+fn par_for_vec_keeps_the_order() {
+    let words: Vec<String> = (0..1000).map(|i| format!("w{}", i * 7 % 1000)).collect();
+
+    let par = par_for_vec! {
+        for w in &words;
+        if w.len() > 2;
+        let n: usize = w[1..].parse().unwrap();
+        if let Some(half) = n.checked_div(2);
+        (w.clone(), half)
+    };
+    let seq = for_vec! {
+        for w in &words;
+        if w.len() > 2;
+        let n: usize = w[1..].parse().unwrap();
+        if let Some(half) = n.checked_div(2);
+        (w.clone(), half)
+    };
+    assert_eq!(par, seq);
+
+    let par = par_for_vec! {
+        for x in 0..100u32;
+        for y in 0..x % 4;
+        (x, y)
+    };
+    let seq = for_vec! {
+        for x in 0..100u32;
+        for y in 0..x % 4;
+        (x, y)
+    };
+    assert_eq!(par, seq);
+}
@@ -0,0 +1,157 @@
+use for_ch::{for_ch, for_iter, for_vec};
+
+#[test]
+fn local_fn_between_clauses() {
+    let mut xs = vec![];
+    for_ch! {
+        for x in 0..2;
+        fn double(x: i32) -> i32 {
+            x * 2
+        }
+        struct Pair(i32, i32);
+        for y in 0..2;
+        let Pair(a, b) = Pair(double(x), double(y));
+        xs.push((a, b));
+    }
+    assert_eq!(xs, vec![(0, 0), (0, 2), (2, 0), (2, 2)]);
+}
+
+#[test]
+fn local_fn_in_iter() {
+    let v: Vec<_> = for_iter! {
+        for x in 0..3;
+        fn square(x: i32) -> i32 {
+            x * x
+        }
+        square(x)
+    }
+    .collect();
+    assert_eq!(v, [0, 1, 4]);
+}
+
+#[test]
+fn let_shadows_for_binding() {
+    let mut xs = vec![];
+    for_ch! {
+        for x in 0..4;
+        let x = x * 10;
+        if x > 10;
+        let x = x + 1;
+        xs.push(x);
+    }
+    assert_eq!(xs, vec![21, 31]);
+
+    let v: Vec<_> = for_iter! {
+        for x in 0..4;
+        let x = x * 10;
+        if x > 10;
+        x
+    }
+    .collect();
+    assert_eq!(v, [20, 30]);
+}
+
+#[test]
+fn for_borrows_let_binding() {
+    let mut xs = vec![];
+    for_ch! {
+        for n in 1..3;
+        let data: Vec<String> = (0..n).map(|i| i.to_string()).collect();
+        for s in data.iter();
+        if let Some(last) = data.last();
+        xs.push(format!("{}{}", s, last));
+    }
+    assert_eq!(xs, vec!["00", "01", "11"]);
+
+    let mut lens = vec![];
+    for_ch! {
+        let words: Vec<&str> = vec!["a", "bc"];
+        for w in words.iter();
+        lens.push((w.len(), words.len()));
+    }
+    assert_eq!(lens, vec![(1, 2), (2, 2)]);
+}
+
+#[test]
+fn destructuring_assignment() {
+    let (mut a, mut b) = (1, 2);
+    let mut seen = vec![];
+    for_ch! {
+        for _ in 0..3;
+        (a, b) = (b, a);
+        seen.push((a, b));
+    }
+    assert_eq!(seen, vec![(2, 1), (1, 2), (2, 1)]);
+
+    let (mut lo, mut hi) = (0, 0);
+    let mut spans = vec![];
+    for_ch! {
+        for x in [3, 1, 4];
+        [lo, hi] = [lo.min(x), hi.max(x)];
+        if x % 2 == 1;
+        spans.push(hi - lo);
+    }
+    assert_eq!(spans, vec![3, 3]);
+}
+
+#[test]
+fn carry_running_max() {
+    let rows = [[3, 1], [2, 7], [5, 4]];
+    let maxes = for_vec! {
+        for row in rows;
+        for x in row;
+        carry best = i32::MIN;
+        best = best.max(x);
+        best
+    };
+    assert_eq!(maxes, vec![3, 3, 3, 7, 7, 7]);
+
+    let mut gains = vec![];
+    for_ch! {
+        carry prev: Option<i32> = None;
+        for x in [1, 4, 2, 8];
+        if let Some(p) = prev {
+            gains.push(x - p);
+        }
+        prev = Some(x);
+    }
+    assert_eq!(gains, vec![3, -2, 6]);
+}
+
+#[test]
+fn carry_survives_continue_for_ch() {
+    let mut xs = 1..7;
+    let mut seen = vec![];
+    for_ch! {
+        carry count = 0;
+        for x in xs.by_ref();
+        count += 1;
+        seen.push((x, count));
+        if x % 3 == 0 {
+            continue 'for_ch;
+        }
+    }
+    assert_eq!(seen, [(1, 1), (2, 2), (3, 3), (4, 4), (5, 5), (6, 6)]);
+}
+
+#[test]
+fn carry_prefix_sums() {
+    let prefix = for_vec! {
+        carry sum = 0;
+        for i, x in [3, 1, 4] enumerate;
+        sum += x;
+        (i, sum)
+    };
+    assert_eq!(prefix, [(0, 3), (1, 4), (2, 8)]);
+
+    // per row, with a `let mut` of the outer loop instead
+    let rows = [vec![1, 2], vec![3, 4, 5]];
+    let prefix = for_vec! {
+        for row in &rows;
+        let mut sum = 0;
+        for i, x in row enumerate;
+        sum += x;
+        (i, sum)
+    };
+    assert_eq!(prefix, [(0, 1), (1, 3), (0, 3), (1, 7), (2, 12)]);
+}
@@ -0,0 +1,121 @@
+#![cfg(feature = "stream")]
+
+use for_ch::{block_on_for_ch, for_ch, for_stream};
+use futures::stream;
+
+#[test]
+fn for_await_stream() {
+    let mut xs = vec![];
+    futures::executor::block_on(async {
+        for_ch! {
+            for await x in stream::iter(0..4);
+            if x % 2 == 0;
+            for y in 0..2;
+            xs.push((x, y));
+        }
+    });
+    assert_eq!(xs, vec![(0, 0), (0, 1), (2, 0), (2, 1)]);
+}
+
+#[test]
+fn block_on_drives_the_chain() {
+    let mut xs = vec![];
+    block_on_for_ch! {
+        for await x in stream::iter(0..3);
+        for y in 0..x;
+        let z = async { x * 10 + y }.await;
+        xs.push(z);
+    }
+    assert_eq!(xs, vec![10, 20, 21]);
+}
+
+#[test]
+fn for_await_refutable() {
+    let mut xs = vec![];
+    futures::executor::block_on(async {
+        for_ch! {
+            for? await Some(x) in stream::iter(vec![Some(1), None, Some(3)]);
+            xs.push(x);
+        }
+    });
+    assert_eq!(xs, vec![1, 3]);
+}
+
+#[cfg(feature = "tokio")]
+#[tokio::test(start_paused = true)]
+async fn for_await_within_deadline() {
+    use std::time::Duration;
+
+    let slow = stream::unfold(0, |i| async move {
+        tokio::time::sleep(Duration::from_secs(1)).await;
+        Some((i, i + 1))
+    });
+
+    let mut xs = vec![];
+    for_ch! {
+        for await x in slow within Duration::from_millis(2500);
+        xs.push(x);
+    }
+    assert_eq!(xs, vec![0, 1]);
+}
+
+#[test]
+fn for_stream_lazy() {
+    use futures::StreamExt;
+
+    let s = for_stream! {
+        for await x in stream::iter(0..4);
+        if x % 2 == 1;
+        for y in 0..x;
+        (x, y)
+    };
+    let xs = futures::executor::block_on(s.collect::<Vec<_>>());
+    assert_eq!(xs, vec![(1, 0), (3, 0), (3, 1), (3, 2)]);
+}
+
+#[test]
+fn for_stream_move_owns_captures() {
+    use futures::{Stream, StreamExt};
+
+    fn owned(n: i32) -> impl Stream<Item = i32> {
+        let picked: Vec<i32> = vec![1, 3, 4];
+        for_stream!(move {
+            for x in 0..n;
+            if x in picked;
+            x * 10
+        })
+    }
+
+    let xs = futures::executor::block_on(owned(4).collect::<Vec<_>>());
+    assert_eq!(xs, vec![10, 30]);
+}
+
+#[test]
+fn for_await_index() {
+    use futures::StreamExt;
+
+    let mut xs = vec![];
+    futures::executor::block_on(async {
+        for_ch! {
+            for await x in stream::iter(['a', 'b']);
+            xs.push(($i, x));
+        }
+    });
+    assert_eq!(xs, vec![(0, 'a'), (1, 'b')]);
+
+    let s = for_stream! {
+        for await x in stream::iter(['a', 'b']);
+        for y in 0..2;
+        ($i0, x, $i, y)
+    };
+    let ys = futures::executor::block_on(s.collect::<Vec<_>>());
+    assert_eq!(
+        ys,
+        vec![
+            (0, 'a', 0, 0),
+            (0, 'a', 1, 1),
+            (1, 'b', 0, 0),
+            (1, 'b', 1, 1)
+        ]
+    );
+}
@@ -0,0 +1,44 @@
+#![cfg(feature = "trace")]
+
+use for_ch::for_ch;
+use std::sync::Mutex;
+
+struct Capture(Mutex<Vec<String>>);
+
+impl log::Log for Capture {
+    fn enabled(&self, _: &log::Metadata) -> bool {
+        true
+    }
+
+    fn log(&self, record: &log::Record) {
+        self.0.lock().unwrap().push(record.args().to_string());
+    }
+
+    fn flush(&self) {}
+}
+
+static LOGS: Capture = Capture(Mutex::new(Vec::new()));
+
+#[test]
+fn failed_guards_are_logged() {
+    log::set_logger(&LOGS).unwrap();
+    log::set_max_level(log::LevelFilter::Trace);
+
+    let mut xs = vec![];
+    for_ch! {
+        for x in 0..4u32;
+        if x > 0;
+        if x % 2 == 1;
+        if let Some(y) = x.checked_sub(3);
+        xs.push(y);
+    }
+    assert_eq!(xs, [0]);
+
+    // the spacing of the source text is up to proc-macro2
+    let logs = LOGS.0.lock().unwrap();
+    assert_eq!(logs.len(), 3);
+    assert_eq!(logs[0], "for_ch: skipped by `if x > 0`");
+    assert!(logs[1].starts_with("for_ch: skipped by `if let Some"));
+    assert!(logs[1].contains("checked_sub"));
+    assert_eq!(logs[2], "for_ch: skipped by `if x % 2 == 1`");
+}
@@ -0,0 +1,209 @@
+use for_ch::{for_try_fold, try_for_ch};
+use std::num::ParseIntError;
+
+#[test]
+fn try_propagates() {
+    let mut parsed = vec![];
+    let r: Result<(), ParseIntError> = try_for_ch! {
+        for s in ["1", "x", "3"];
+        parsed.push(s.parse::<i32>()?);
+    };
+    assert!(r.is_err());
+    assert_eq!(parsed, vec![1]);
+}
+
+#[test]
+fn try_break_for_ch() {
+    let mut parsed = vec![];
+    let r: Result<usize, ParseIntError> = try_for_ch! {
+        for s in ["1", "2", "stop", "x"];
+        if s == "stop" {
+            break 'for_ch;
+        }
+        parsed.push(s.parse::<i32>()?);
+        parsed.len()
+    };
+    assert_eq!(r, Ok(2));
+    assert_eq!(parsed, vec![1, 2]);
+}
+
+#[test]
+fn try_each_element() {
+    let mut seen = vec![];
+    let r: Result<(), ParseIntError> = try_for_ch! {
+        for x? in ["1", "2", "x", "4"].iter().map(|s| s.parse::<i32>());
+        seen.push(x);
+    };
+    assert_eq!(r.unwrap_err().to_string(), "invalid digit found in string");
+    assert_eq!(seen, vec![1, 2]);
+
+    let results: Vec<Result<(i32, i32), String>> = vec![Ok((1, 2)), Ok((3, 4))];
+    let mut sums = vec![];
+    let r: Result<usize, String> = try_for_ch! {
+        for i in 0.., for (a, b)? in results;
+        sums.push(i + a + b);
+        sums.len()
+    };
+    assert_eq!(r, Ok(2));
+    assert_eq!(sums, vec![3, 8]);
+}
+
+#[test]
+fn try_read_dir_entries() {
+    let dir = std::env::temp_dir().join(format!("for_ch_read_dir_{}", std::process::id()));
+    std::fs::create_dir_all(dir.join("sub")).unwrap();
+    std::fs::write(dir.join("a.txt"), "a").unwrap();
+    std::fs::write(dir.join("b.txt"), "bb").unwrap();
+
+    let list = |dir: &std::path::Path| -> std::io::Result<Vec<(String, u64)>> {
+        let mut files = vec![];
+        let r: std::io::Result<()> = try_for_ch! {
+            for entry? in std::fs::read_dir(dir)?;
+            let meta = entry.metadata()?;
+            if meta.is_file();
+            files.push((entry.file_name().into_string().unwrap(), meta.len()));
+        };
+        r?;
+        files.sort();
+        Ok(files)
+    };
+
+    let files = list(&dir);
+    let missing = list(&dir.join("missing"));
+    std::fs::remove_dir_all(&dir).unwrap();
+
+    assert_eq!(
+        files.unwrap(),
+        vec![("a.txt".to_string(), 1), ("b.txt".to_string(), 2)]
+    );
+    assert_eq!(missing.unwrap_err().kind(), std::io::ErrorKind::NotFound);
+}
+
+#[test]
+fn try_tail_result() {
+    let mut total = 0;
+    let r: Result<i32, ParseIntError> = try_for_ch! {
+        for s in ["1", "2", "3"];
+        total += s.parse::<i32>()?;
+        total
+    };
+    assert_eq!(r, Ok(6));
+
+    let r: Result<i32, ParseIntError> = try_for_ch! {
+        for s in ["1", "2"];
+        for t in ["3", "4"];
+        total += s.parse::<i32>()? * t.parse::<i32>()?;
+        total
+    };
+    assert_eq!(r, Ok(6 + 3 + 4 + 6 + 8));
+}
+
+#[test]
+fn try_do_block() {
+    fn setup(s: &str, log: &mut Vec<i32>) -> Result<(), ParseIntError> {
+        log.push(s.parse()?);
+        Ok(())
+    }
+
+    let mut log = vec![];
+    let r: Result<(), ParseIntError> = try_for_ch! {
+        for s in ["1", "2", "x", "4"];
+        do {
+            setup(s, &mut log)?;
+            log.push(0);
+        }
+        log.push(-1);
+    };
+    assert!(r.is_err());
+    assert_eq!(log, vec![1, 0, -1, 2, 0, -1]);
+}
+
+#[test]
+fn try_if_let_else_err() {
+    #[derive(Debug, PartialEq)]
+    struct MyError(String);
+
+    impl From<ParseIntError> for MyError {
+        fn from(err: ParseIntError) -> Self {
+            MyError(err.to_string())
+        }
+    }
+
+    let mut parsed = vec![];
+    let r: Result<(), MyError> = try_for_ch! {
+        for s in ["1", "2", "x", "4"];
+        if let Ok(x) = s.parse::<i32>() else err;
+        parsed.push(x);
+    };
+    assert_eq!(r, Err(MyError("invalid digit found in string".to_owned())));
+    assert_eq!(parsed, vec![1, 2]);
+
+    // an `Ok` that doesn't match is skipped
+    let mut positives = vec![];
+    let r: Result<(), MyError> = try_for_ch! {
+        for s in ["3", "0", "7", "x", "9"];
+        if let Ok(Some(n)) = s.parse::<i32>().map(|n| Some(n).filter(|&n| n > 0)) else err;
+        positives.push(n);
+    };
+    assert_eq!(r, Err(MyError("invalid digit found in string".to_owned())));
+    assert_eq!(positives, vec![3, 7]);
+}
+
+#[test]
+fn try_fold_ok() {
+    let r: Result<i32, ParseIntError> = for_try_fold!(0, acc, {
+        for s in ["1", "2", "3"];
+        for t in [s, "10"];
+        acc + t.parse::<i32>()?
+    });
+    assert_eq!(r, Ok(36));
+
+    let r: Result<Vec<u8>, ParseIntError> = for_try_fold!(vec![], digits, {
+        for s in ["1", "2"];
+        if s != "2";
+        {
+            let mut more = digits;
+            more.push(s.parse()?);
+            more
+        }
+    });
+    assert_eq!(r, Ok(vec![1]));
+}
+
+#[test]
+fn try_fold_stops_at_the_first_error() {
+    let mut seen = vec![];
+    let r: Result<i32, ParseIntError> = for_try_fold!(0, acc, {
+        for s in ["1", "x", "3", "y"];
+        seen.push(s);
+        acc + s.parse::<i32>()?
+    });
+    assert!(r.is_err());
+    assert_eq!(seen, ["1", "x"]);
+}
+
+#[test]
+fn fallible_body_statement() {
+    struct Sink {
+        writes: usize,
+    }
+
+    impl Sink {
+        fn write(&mut self, x: i32) -> Result<(), String> {
+            self.writes += 1;
+            if self.writes == 3 {
+                Err(format!("full at {}", x))
+            } else {
+                Ok(())
+            }
+        }
+    }
+
+    let mut sink = Sink { writes: 0 };
+    let r: Result<(), String> = try_for_ch! {
+        for x in 10..20;
+        sink.write(x)?;
+    };
+    assert_eq!(r, Err("full at 12".to_string()));
+    assert_eq!(sink.writes, 3);
+}